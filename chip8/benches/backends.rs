@@ -0,0 +1,52 @@
+//! Compares the instructions per second of the backends on the bundled
+//! roms, with `cargo bench -p chip8`.
+
+use std::fs;
+use std::path::Path;
+
+use chip8::{Backend, Chip8};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+
+const CYCLES: u64 = 100_000;
+/// Instructions per frame, high so that the timers don't hold the roms
+/// waiting for too long
+const IPF: u64 = 1000;
+
+fn backends(c: &mut Criterion) {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../roms");
+    let mut roms: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    roms.sort();
+    for path in roms {
+        let rom = fs::read(&path).unwrap();
+        let name = path.file_name().unwrap().to_string_lossy();
+        let mut group = c.benchmark_group(name);
+        group.throughput(Throughput::Elements(CYCLES));
+        for backend in [Backend::Interpreter, Backend::Cached] {
+            let mut chip8 = Chip8::new().with_rng(0).with_backend(backend);
+            chip8.load_rom(&rom).unwrap();
+            group.bench_function(backend.to_string(), |b| {
+                b.iter_batched_ref(
+                    || chip8.clone(),
+                    |chip8| {
+                        // unlike `run_frame`, a rom waiting for a key keeps
+                        // running FX0A
+                        for i in 0..CYCLES {
+                            chip8.step().unwrap();
+                            if i % IPF == 0 {
+                                chip8.tick_timers();
+                            }
+                        }
+                    },
+                    BatchSize::SmallInput,
+                )
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, backends);
+criterion_main!(benches);
@@ -0,0 +1,726 @@
+//! Assembler for a subset of Octo (https://github.com/JohnEarnest/Octo),
+//! the language most CHIP-8 games are written in:
+//!
+//! ```
+//! let rom = chip8::asm::assemble(
+//!     ": main
+//!         v0 := 5
+//!         loop
+//!             v0 += -1
+//!             if v0 == 0 then return
+//!         again",
+//! )
+//! .unwrap();
+//! assert_eq!(rom, [0x60, 0x05, 0x70, 0xFF, 0x40, 0x00, 0x00, 0xEE, 0x12, 0x02]);
+//! ```
+//!
+//! It has labels (`: name`), `:const`, `:alias`, `:call`, `:byte`, raw
+//! bytes, the instructions of CHIP-8, SUPER-CHIP and XO-CHIP with the Octo
+//! syntax, `if ... then`, `if ... begin ... else ... end` and
+//! `loop ... while ... again`. A bare label name calls it. The program runs
+//! from `main`, a jump to it is added when it isn't at the start.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use crate::instruction::Instruction;
+use crate::PROGRAM_START;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsmErrorKind {
+    /// The source ended in the middle of a statement
+    UnexpectedEnd,
+    /// A token that doesn't fit where it was found
+    UnexpectedToken(String),
+    /// A register was expected, v0 to vF or an alias
+    ExpectedRegister(String),
+    /// Neither a number nor a constant
+    InvalidNumber(String),
+    /// A number that doesn't fit in its field
+    OutOfRange {
+        value: i64,
+        min: i64,
+        max: i64,
+    },
+    UndefinedLabel(String),
+    /// A label, constant or alias defined twice
+    Redefined(String),
+    /// `else`, `end`, `while` or `again` without the block they belong to,
+    /// or a block that is never closed
+    UnbalancedBlock(String),
+}
+
+/// An assembly error, at the line and column (both from 1) of the token
+/// that caused it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: AsmErrorKind,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}: ", self.line, self.column)?;
+        match &self.kind {
+            AsmErrorKind::UnexpectedEnd => write!(f, "unexpected end of the source"),
+            AsmErrorKind::UnexpectedToken(s) => write!(f, "unexpected '{}'", s),
+            AsmErrorKind::ExpectedRegister(s) => write!(f, "expected a register, found '{}'", s),
+            AsmErrorKind::InvalidNumber(s) => write!(f, "'{}' is not a number or constant", s),
+            AsmErrorKind::OutOfRange { value, min, max } => {
+                write!(f, "{} is out of range, from {} to {}", value, min, max)
+            }
+            AsmErrorKind::UndefinedLabel(s) => write!(f, "undefined label '{}'", s),
+            AsmErrorKind::Redefined(s) => write!(f, "'{}' is already defined", s),
+            AsmErrorKind::UnbalancedBlock(s) => write!(f, "unbalanced '{}'", s),
+        }
+    }
+}
+
+impl Error for AsmError {}
+
+/// Assembles Octo source into a rom loaded at `PROGRAM_START`
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    let tokens = tokenize(source);
+    let (rom, labels) = Assembler::new(tokens.clone(), PROGRAM_START).run()?;
+    match labels.get("main") {
+        Some(&main) if main as usize != PROGRAM_START => {
+            let (rom, labels) = Assembler::new(tokens.clone(), PROGRAM_START + 2).run()?;
+            let nnn = labels["main"];
+            if nnn > 0xFFF {
+                // the first jump is out of reach of 1NNN
+                let name = tokens
+                    .windows(2)
+                    .find(|t| t[0].text == ":" && t[1].text == "main")
+                    .map(|t| &t[1])
+                    .unwrap();
+                return Err(name.error(AsmErrorKind::OutOfRange {
+                    value: nnn as i64,
+                    min: 0,
+                    max: 0xFFF,
+                }));
+            }
+            let mut prefixed = Instruction::Jump { nnn }.bytes();
+            prefixed.extend(rom);
+            Ok(prefixed)
+        }
+        _ => Ok(rom),
+    }
+}
+
+/// Words that can't be the name of a label, constant or alias
+#[rustfmt::skip]
+const RESERVED: [&str; 50] = [
+    "then", "begin", "key", "-key", "==", "!=", ":=", "+=", "-=", "=-", "|=", "&=", "^=", ">>=",
+    "<<=", "-", "random", "delay", "hex", "bighex", "long", ";", "clear", "return", "exit",
+    "lores", "hires", "scroll-down", "scroll-up", "scroll-left", "scroll-right", "jump", "jump0",
+    "bcd", "save", "load", "saveflags", "loadflags", "sprite", "plane", "audio", "buzzer", "pitch",
+    "i", "if", "else", "end", "loop", "while", "again",
+];
+
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    line: usize,
+    column: usize,
+}
+
+impl Token {
+    fn error(&self, kind: AsmErrorKind) -> AsmError {
+        AsmError {
+            line: self.line,
+            column: self.column,
+            kind,
+        }
+    }
+    fn unexpected(&self) -> AsmError {
+        self.error(AsmErrorKind::UnexpectedToken(self.text.clone()))
+    }
+}
+
+/// Splits the source on whitespace, `#` starts a comment
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    for (i, line) in source.lines().enumerate() {
+        let mut text = String::new();
+        let mut column = 0;
+        for (c, ch) in line.chars().enumerate() {
+            if ch == '#' {
+                break;
+            }
+            if ch.is_whitespace() {
+                if !text.is_empty() {
+                    tokens.push(Token {
+                        text: std::mem::take(&mut text),
+                        line: i + 1,
+                        column,
+                    });
+                }
+            } else {
+                if text.is_empty() {
+                    column = c + 1;
+                }
+                text.push(ch);
+            }
+        }
+        if !text.is_empty() {
+            tokens.push(Token {
+                text,
+                line: i + 1,
+                column,
+            });
+        }
+    }
+    tokens
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let (digits, radix) = if let Some(hex) = digits.strip_prefix("0x") {
+        (hex, 16)
+    } else if let Some(bin) = digits.strip_prefix("0b") {
+        (bin, 2)
+    } else {
+        (digits, 10)
+    };
+    // from_str_radix also takes a sign
+    if digits.starts_with(['+', '-']) {
+        return None;
+    }
+    let value = i64::from_str_radix(digits, radix).ok()?;
+    Some(if negative { -value } else { value })
+}
+
+/// An address operand, known or to be resolved once all labels are defined
+enum Target {
+    Addr(u16),
+    Label(Token),
+}
+
+enum Emit {
+    Byte(u8),
+    Op(Instruction),
+    /// An instruction with an address operand
+    Ref {
+        op: fn(u16) -> Instruction,
+        target: Target,
+    },
+}
+
+impl Emit {
+    fn size(&self) -> usize {
+        match self {
+            Emit::Byte(_) => 1,
+            Emit::Op(instruction) => instruction.size(),
+            Emit::Ref { op, .. } => op(0).size(),
+        }
+    }
+}
+
+/// Blocks whose jumps are patched when they are closed
+enum Block {
+    /// `if ... begin`, with the jump over the block or the `else` branch
+    If { jump: usize, token: Token },
+    /// `loop`, with the jumps out of the loop of its `while`s
+    Loop {
+        start: u16,
+        breaks: Vec<usize>,
+        token: Token,
+    },
+}
+
+struct Assembler {
+    tokens: Vec<Token>,
+    pos: usize,
+    out: Vec<Emit>,
+    /// Address of the next byte
+    here: usize,
+    labels: HashMap<String, u16>,
+    consts: HashMap<String, i64>,
+    aliases: HashMap<String, u8>,
+    blocks: Vec<Block>,
+}
+
+impl Assembler {
+    fn new(tokens: Vec<Token>, origin: usize) -> Assembler {
+        Assembler {
+            tokens,
+            pos: 0,
+            out: Vec::new(),
+            here: origin,
+            labels: HashMap::new(),
+            consts: HashMap::new(),
+            aliases: HashMap::new(),
+            blocks: Vec::new(),
+        }
+    }
+    fn run(mut self) -> Result<(Vec<u8>, HashMap<String, u16>), AsmError> {
+        while self.pos < self.tokens.len() {
+            let token = self.next()?;
+            self.statement(token)?;
+        }
+        if let Some(block) = self.blocks.last() {
+            let token = match block {
+                Block::If { token, .. } | Block::Loop { token, .. } => token,
+            };
+            return Err(token.error(AsmErrorKind::UnbalancedBlock(token.text.clone())));
+        }
+        let mut rom = Vec::new();
+        for emit in &self.out {
+            match emit {
+                Emit::Byte(b) => rom.push(*b),
+                Emit::Op(instruction) => rom.extend(instruction.bytes()),
+                Emit::Ref { op, target } => {
+                    let addr = match target {
+                        Target::Addr(addr) => *addr,
+                        Target::Label(token) => match self.labels.get(&token.text) {
+                            Some(addr) => *addr,
+                            None => {
+                                return Err(
+                                    token.error(AsmErrorKind::UndefinedLabel(token.text.clone()))
+                                )
+                            }
+                        },
+                    };
+                    let max = if op(0).size() == 4 { 0xFFFF } else { 0xFFF };
+                    if addr > max {
+                        let token = match target {
+                            Target::Label(token) => token,
+                            Target::Addr(_) => unreachable!("checked when parsed"),
+                        };
+                        return Err(token.error(AsmErrorKind::OutOfRange {
+                            value: addr as i64,
+                            min: 0,
+                            max: max as i64,
+                        }));
+                    }
+                    rom.extend(op(addr).bytes());
+                }
+            }
+        }
+        Ok((rom, self.labels))
+    }
+
+    fn next(&mut self) -> Result<Token, AsmError> {
+        match self.tokens.get(self.pos) {
+            Some(token) => {
+                self.pos += 1;
+                Ok(token.clone())
+            }
+            None => {
+                let (line, column) = self
+                    .tokens
+                    .last()
+                    .map_or((1, 1), |t| (t.line, t.column + t.text.chars().count()));
+                Err(AsmError {
+                    line,
+                    column,
+                    kind: AsmErrorKind::UnexpectedEnd,
+                })
+            }
+        }
+    }
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|t| t.text.as_str())
+    }
+    fn expect(&mut self, text: &str) -> Result<(), AsmError> {
+        let token = self.next()?;
+        if token.text != text {
+            return Err(token.unexpected());
+        }
+        Ok(())
+    }
+    fn emit(&mut self, emit: Emit) -> usize {
+        self.here += emit.size();
+        self.out.push(emit);
+        self.out.len() - 1
+    }
+    fn op(&mut self, instruction: Instruction) {
+        self.emit(Emit::Op(instruction));
+    }
+    /// The current address as the operand of a jump
+    fn here_nnn(&self, token: &Token) -> Result<u16, AsmError> {
+        if self.here > 0xFFF {
+            return Err(token.error(AsmErrorKind::OutOfRange {
+                value: self.here as i64,
+                min: 0,
+                max: 0xFFF,
+            }));
+        }
+        Ok(self.here as u16)
+    }
+    /// Points the jump emitted at `index` to the current address
+    fn patch(&mut self, index: usize, token: &Token) -> Result<(), AsmError> {
+        self.out[index] = Emit::Op(Instruction::Jump {
+            nnn: self.here_nnn(token)?,
+        });
+        Ok(())
+    }
+
+    fn register_of(&self, text: &str) -> Option<u8> {
+        if let Some(&x) = self.aliases.get(text) {
+            return Some(x);
+        }
+        let mut chars = text.chars();
+        match (chars.next(), chars.next(), chars.next()) {
+            (Some('v' | 'V'), Some(digit), None) => digit.to_digit(16).map(|x| x as u8),
+            _ => None,
+        }
+    }
+    fn register(&mut self) -> Result<u8, AsmError> {
+        let token = self.next()?;
+        self.register_of(&token.text)
+            .ok_or_else(|| token.error(AsmErrorKind::ExpectedRegister(token.text.clone())))
+    }
+    fn number_of(&self, token: &Token) -> Result<i64, AsmError> {
+        parse_number(&token.text)
+            .or_else(|| self.consts.get(&token.text).copied())
+            .ok_or_else(|| token.error(AsmErrorKind::InvalidNumber(token.text.clone())))
+    }
+    fn ranged_of(&self, token: &Token, min: i64, max: i64) -> Result<i64, AsmError> {
+        let value = self.number_of(token)?;
+        if value < min || value > max {
+            return Err(token.error(AsmErrorKind::OutOfRange { value, min, max }));
+        }
+        Ok(value)
+    }
+    /// A byte, negative numbers are stored in two's complement
+    fn byte_of(&self, token: &Token) -> Result<u8, AsmError> {
+        Ok(self.ranged_of(token, -128, 255)? as u8)
+    }
+    fn byte(&mut self) -> Result<u8, AsmError> {
+        let token = self.next()?;
+        self.byte_of(&token)
+    }
+    fn nibble(&mut self) -> Result<u8, AsmError> {
+        let token = self.next()?;
+        Ok(self.ranged_of(&token, 0, 15)? as u8)
+    }
+    /// An address operand, labels can be defined later
+    fn target(&mut self) -> Result<Target, AsmError> {
+        let token = self.next()?;
+        if parse_number(&token.text).is_some() || self.consts.contains_key(&token.text) {
+            return Ok(Target::Addr(self.ranged_of(&token, 0, 0xFFFF)? as u16));
+        }
+        Ok(Target::Label(token))
+    }
+    fn reference(&mut self, op: fn(u16) -> Instruction) -> Result<(), AsmError> {
+        let target = self.target()?;
+        if let Target::Addr(addr) = target {
+            let max = if op(0).size() == 4 { 0xFFFF } else { 0xFFF };
+            if addr > max {
+                return Err(self.tokens[self.pos - 1].error(AsmErrorKind::OutOfRange {
+                    value: addr as i64,
+                    min: 0,
+                    max: max as i64,
+                }));
+            }
+        }
+        self.emit(Emit::Ref { op, target });
+        Ok(())
+    }
+    /// A name that is not yet a label, constant or alias
+    fn new_name(&mut self) -> Result<Token, AsmError> {
+        let token = self.next()?;
+        let name = &token.text;
+        if self.labels.contains_key(name)
+            || self.consts.contains_key(name)
+            || self.aliases.contains_key(name)
+        {
+            return Err(token.error(AsmErrorKind::Redefined(name.clone())));
+        }
+        // a register or number would always be read as such
+        if name.starts_with(':')
+            || RESERVED.contains(&name.as_str())
+            || self.register_of(name).is_some()
+            || parse_number(name).is_some()
+        {
+            return Err(token.unexpected());
+        }
+        Ok(token)
+    }
+
+    /// The skips taken when the condition holds and when it doesn't
+    fn condition(&mut self) -> Result<(Instruction, Instruction), AsmError> {
+        use Instruction::*;
+        let x = self.register()?;
+        let op = self.next()?;
+        match op.text.as_str() {
+            "key" => Ok((SkipKey { x }, SkipNotKey { x })),
+            "-key" => Ok((SkipNotKey { x }, SkipKey { x })),
+            "==" | "!=" => {
+                let rhs = self.next()?;
+                let (eq, ne) = match self.register_of(&rhs.text) {
+                    Some(y) => (SkipEqReg { x, y }, SkipNeReg { x, y }),
+                    None => {
+                        let nn = self.byte_of(&rhs)?;
+                        (SkipEqByte { x, nn }, SkipNeByte { x, nn })
+                    }
+                };
+                if op.text == "==" {
+                    Ok((eq, ne))
+                } else {
+                    Ok((ne, eq))
+                }
+            }
+            _ => Err(op.unexpected()),
+        }
+    }
+
+    fn statement(&mut self, token: Token) -> Result<(), AsmError> {
+        use Instruction::*;
+        match token.text.as_str() {
+            ":" => {
+                let name = self.new_name()?;
+                if self.here > 0xFFFF {
+                    return Err(name.error(AsmErrorKind::OutOfRange {
+                        value: self.here as i64,
+                        min: 0,
+                        max: 0xFFFF,
+                    }));
+                }
+                self.labels.insert(name.text, self.here as u16);
+            }
+            ":const" => {
+                let name = self.new_name()?;
+                let value = self.next()?;
+                let value = self.number_of(&value)?;
+                self.consts.insert(name.text, value);
+            }
+            ":alias" => {
+                let name = self.new_name()?;
+                let x = self.register()?;
+                self.aliases.insert(name.text, x);
+            }
+            ":call" => self.reference(|nnn| Call { nnn })?,
+            ":byte" => {
+                let b = self.byte()?;
+                self.emit(Emit::Byte(b));
+            }
+            "clear" => self.op(Cls),
+            "return" | ";" => self.op(Ret),
+            "exit" => self.op(Exit),
+            "lores" => self.op(Lores),
+            "hires" => self.op(Hires),
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.op(ScrollDown { n });
+            }
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.op(ScrollUp { n });
+            }
+            "scroll-left" => self.op(ScrollLeft),
+            "scroll-right" => self.op(ScrollRight),
+            "jump" => self.reference(|nnn| Jump { nnn })?,
+            "jump0" => self.reference(|nnn| JumpV0 { nnn })?,
+            "bcd" => {
+                let x = self.register()?;
+                self.op(Bcd { x });
+            }
+            "save" | "load" => {
+                let x = self.register()?;
+                let instruction = if self.peek() == Some("-") {
+                    self.pos += 1;
+                    let y = self.register()?;
+                    if token.text == "save" {
+                        SaveRange { x, y }
+                    } else {
+                        LoadRange { x, y }
+                    }
+                } else if token.text == "save" {
+                    Store { x }
+                } else {
+                    Load { x }
+                };
+                self.op(instruction);
+            }
+            "saveflags" => {
+                let x = self.register()?;
+                self.op(SaveFlags { x });
+            }
+            "loadflags" => {
+                let x = self.register()?;
+                self.op(LoadFlags { x });
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                self.op(Draw { x, y, n });
+            }
+            "plane" => {
+                let n = self.nibble()?;
+                if n > 3 {
+                    return Err(self.tokens[self.pos - 1].error(AsmErrorKind::OutOfRange {
+                        value: n as i64,
+                        min: 0,
+                        max: 3,
+                    }));
+                }
+                self.op(Plane { n });
+            }
+            "audio" => self.op(Audio),
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                self.op(match token.text.as_str() {
+                    "delay" => SetDelay { x },
+                    "buzzer" => SetSound { x },
+                    _ => Pitch { x },
+                });
+            }
+            "i" => {
+                let op = self.next()?;
+                match op.text.as_str() {
+                    ":=" => match self.peek() {
+                        Some("hex") => {
+                            self.pos += 1;
+                            let x = self.register()?;
+                            self.op(Font { x });
+                        }
+                        Some("bighex") => {
+                            self.pos += 1;
+                            let x = self.register()?;
+                            self.op(BigFont { x });
+                        }
+                        Some("long") => {
+                            self.pos += 1;
+                            self.reference(|nnnn| LoadILong { nnnn })?;
+                        }
+                        _ => self.reference(|nnn| LoadI { nnn })?,
+                    },
+                    "+=" => {
+                        let x = self.register()?;
+                        self.op(AddI { x });
+                    }
+                    _ => return Err(op.unexpected()),
+                }
+            }
+            "if" => {
+                let (skip_true, skip_false) = self.condition()?;
+                let then = self.next()?;
+                match then.text.as_str() {
+                    "then" => self.op(skip_false),
+                    "begin" => {
+                        self.op(skip_true);
+                        let jump = self.emit(Emit::Op(Jump { nnn: 0 }));
+                        self.blocks.push(Block::If { jump, token });
+                    }
+                    _ => return Err(then.unexpected()),
+                }
+            }
+            "else" => match self.blocks.pop() {
+                Some(Block::If { jump, token: begin }) => {
+                    let end = self.emit(Emit::Op(Jump { nnn: 0 }));
+                    self.patch(jump, &token)?;
+                    self.blocks.push(Block::If {
+                        jump: end,
+                        token: begin,
+                    });
+                }
+                _ => return Err(token.error(AsmErrorKind::UnbalancedBlock(token.text.clone()))),
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::If { jump, .. }) => self.patch(jump, &token)?,
+                _ => return Err(token.error(AsmErrorKind::UnbalancedBlock(token.text.clone()))),
+            },
+            "loop" => {
+                let start = self.here_nnn(&token)?;
+                self.blocks.push(Block::Loop {
+                    start,
+                    breaks: Vec::new(),
+                    token,
+                });
+            }
+            "while" => {
+                let (skip_true, _) = self.condition()?;
+                self.op(skip_true);
+                let jump = self.emit(Emit::Op(Jump { nnn: 0 }));
+                match self
+                    .blocks
+                    .iter_mut()
+                    .rev()
+                    .find(|b| matches!(b, Block::Loop { .. }))
+                {
+                    Some(Block::Loop { breaks, .. }) => breaks.push(jump),
+                    _ => return Err(token.error(AsmErrorKind::UnbalancedBlock(token.text.clone()))),
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop { start, breaks, .. }) => {
+                    self.op(Jump { nnn: start });
+                    for jump in breaks {
+                        self.patch(jump, &token)?;
+                    }
+                }
+                _ => return Err(token.error(AsmErrorKind::UnbalancedBlock(token.text.clone()))),
+            },
+            text => {
+                if let Some(x) = self.register_of(text) {
+                    self.assignment(x)?;
+                } else if parse_number(text).is_some() || self.consts.contains_key(text) {
+                    let b = self.byte_of(&token)?;
+                    self.emit(Emit::Byte(b));
+                } else if text.starts_with(':') || RESERVED.contains(&text) {
+                    return Err(token.unexpected());
+                } else {
+                    self.emit(Emit::Ref {
+                        op: |nnn| Call { nnn },
+                        target: Target::Label(token),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The statements starting with register `x`
+    fn assignment(&mut self, x: u8) -> Result<(), AsmError> {
+        use Instruction::*;
+        let op = self.next()?;
+        let rhs = self.next()?;
+        let y = self.register_of(&rhs.text);
+        let instruction = match (op.text.as_str(), y) {
+            (":=", Some(y)) => LoadReg { x, y },
+            (":=", None) => match rhs.text.as_str() {
+                "random" => Random {
+                    x,
+                    nn: self.byte()?,
+                },
+                "delay" => LoadDelay { x },
+                "key" => WaitKey { x },
+                _ => LoadByte {
+                    x,
+                    nn: self.byte_of(&rhs)?,
+                },
+            },
+            ("+=", Some(y)) => AddReg { x, y },
+            ("+=", None) => AddByte {
+                x,
+                nn: self.byte_of(&rhs)?,
+            },
+            ("-=", Some(y)) => Sub { x, y },
+            ("-=", None) => AddByte {
+                x,
+                nn: self.byte_of(&rhs)?.wrapping_neg(),
+            },
+            ("=-", Some(y)) => SubN { x, y },
+            ("|=", Some(y)) => Or { x, y },
+            ("&=", Some(y)) => And { x, y },
+            ("^=", Some(y)) => Xor { x, y },
+            (">>=", Some(y)) => Shr { x, y },
+            ("<<=", Some(y)) => Shl { x, y },
+            ("=-" | "|=" | "&=" | "^=" | ">>=" | "<<=", None) => {
+                return Err(rhs.error(AsmErrorKind::ExpectedRegister(rhs.text.clone())))
+            }
+            _ => return Err(op.unexpected()),
+        };
+        self.op(instruction);
+        Ok(())
+    }
+}
@@ -0,0 +1,144 @@
+//! Execution backends. The interpreter fetches and decodes every
+//! instruction it runs, the cached backend decodes the runs of
+//! instructions up to the next branch once, and keeps them until the
+//! memory they were read from is written.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::Instruction;
+
+/// How `step` gets the instructions it executes. Both backends behave the
+/// same, the cached one is faster on long runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// Decodes the opcode at `pc` on every step
+    #[default]
+    Interpreter,
+    /// Reuses the blocks of instructions already decoded from `pc`
+    Cached,
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Backend::Interpreter => write!(f, "interpreter"),
+            Backend::Cached => write!(f, "cached"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownBackend(String);
+
+impl fmt::Display for UnknownBackend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "unknown backend '{}', expected one of: interpreter, cached",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for UnknownBackend {}
+
+impl FromStr for Backend {
+    type Err = UnknownBackend;
+
+    fn from_str(s: &str) -> Result<Backend, UnknownBackend> {
+        match s.to_lowercase().as_str() {
+            "interpreter" => Ok(Backend::Interpreter),
+            "cached" => Ok(Backend::Cached),
+            _ => Err(UnknownBackend(s.to_string())),
+        }
+    }
+}
+
+/// Instructions decoded ahead by the cached backend, by the address they
+/// were read from. A miss decodes the block of instructions from there up
+/// to the next jump, call, return or skip, and a write to any of them
+/// drops the whole block. Only instructions supported by the platform are
+/// kept, so they can run without checks
+#[derive(Clone, Default)]
+pub(crate) struct DecodeCache {
+    entries: Vec<Option<Entry>>,
+}
+
+#[derive(Clone, Copy)]
+struct Entry {
+    instruction: Instruction,
+    opcode: u16,
+    /// Address of the first instruction of the block
+    block: u16,
+}
+
+/// Whether the instruction after this one may not be the next to run
+fn ends_block(instruction: &Instruction) -> bool {
+    use Instruction::*;
+    matches!(
+        instruction,
+        Jump { .. } | Call { .. } | Ret | JumpV0 { .. } | Exit
+    ) || instruction.is_skip()
+}
+
+impl DecodeCache {
+    /// Cache for a memory of `len` bytes, 0 for none
+    pub(crate) fn new(len: usize) -> DecodeCache {
+        DecodeCache {
+            entries: vec![None; len],
+        }
+    }
+    /// The instruction at `pc` and its opcode, if it was decoded
+    pub(crate) fn get(&self, pc: usize) -> Option<(Instruction, u16)> {
+        let entry = self.entries.get(pc).copied().flatten()?;
+        Some((entry.instruction, entry.opcode))
+    }
+    /// Decodes the block starting at `pc` with `fetch`, which gives the
+    /// instruction at an address if the platform has it. The block also
+    /// ends before the instructions already in another one
+    pub(crate) fn decode(&mut self, pc: usize, fetch: impl Fn(usize) -> Option<Instruction>) {
+        let mut addr = pc;
+        while addr == pc || self.entries.get(addr).is_some_and(|entry| entry.is_none()) {
+            let instruction = match fetch(addr) {
+                Some(instruction) => instruction,
+                None => break,
+            };
+            self.entries[addr] = Some(Entry {
+                instruction,
+                opcode: instruction.encode(),
+                block: pc as u16,
+            });
+            addr += instruction.size();
+            if ends_block(&instruction) {
+                break;
+            }
+        }
+    }
+    /// Forgets the blocks of the instructions overlapping the `len` bytes
+    /// written at `addr`, which start up to 3 bytes before as F000 NNNN is
+    /// 4 bytes long
+    pub(crate) fn invalidate(&mut self, addr: usize, len: usize) {
+        let end = (addr + len).min(self.entries.len());
+        for start in addr.saturating_sub(3).min(end)..end {
+            if let Some(entry) = self.entries[start] {
+                if start + entry.instruction.size() > addr {
+                    self.drop_block(entry.block as usize);
+                }
+            }
+        }
+    }
+    fn drop_block(&mut self, block: usize) {
+        let mut addr = block;
+        while let Some(&Some(entry)) = self.entries.get(addr) {
+            if entry.block as usize != block {
+                break;
+            }
+            self.entries[addr] = None;
+            addr += entry.instruction.size();
+        }
+    }
+    pub(crate) fn clear(&mut self) {
+        self.entries.iter_mut().for_each(|entry| *entry = None);
+    }
+}
@@ -0,0 +1,238 @@
+//! Disassembly of rom images with Cowgod's mnemonics
+//! (http://devernay.free.fr/hacks/chip8/C8TECH10.HTM), extended with the
+//! SUPER-CHIP and XO-CHIP instructions.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+pub use crate::instruction::{decode, decode_at, Instruction};
+
+impl Instruction {
+    /// Formats the instruction, using `addr` to print the addresses it
+    /// refers to
+    pub fn format_with(&self, addr: &dyn Fn(u16) -> String) -> String {
+        use Instruction::*;
+        match *self {
+            Sys { nnn } => format!("SYS {}", addr(nnn)),
+            Cls => "CLS".to_string(),
+            Ret => "RET".to_string(),
+            ScrollDown { n } => format!("SCD {}", n),
+            ScrollUp { n } => format!("SCU {}", n),
+            ScrollRight => "SCR".to_string(),
+            ScrollLeft => "SCL".to_string(),
+            Exit => "EXIT".to_string(),
+            Lores => "LOW".to_string(),
+            Hires => "HIGH".to_string(),
+            Jump { nnn } => format!("JP {}", addr(nnn)),
+            Call { nnn } => format!("CALL {}", addr(nnn)),
+            SkipEqByte { x, nn } => format!("SE V{:X}, 0x{:02X}", x, nn),
+            SkipNeByte { x, nn } => format!("SNE V{:X}, 0x{:02X}", x, nn),
+            SkipEqReg { x, y } => format!("SE V{:X}, V{:X}", x, y),
+            SaveRange { x, y } => format!("SAVE V{:X} - V{:X}", x, y),
+            LoadRange { x, y } => format!("LOAD V{:X} - V{:X}", x, y),
+            LoadByte { x, nn } => format!("LD V{:X}, 0x{:02X}", x, nn),
+            AddByte { x, nn } => format!("ADD V{:X}, 0x{:02X}", x, nn),
+            LoadReg { x, y } => format!("LD V{:X}, V{:X}", x, y),
+            Or { x, y } => format!("OR V{:X}, V{:X}", x, y),
+            And { x, y } => format!("AND V{:X}, V{:X}", x, y),
+            Xor { x, y } => format!("XOR V{:X}, V{:X}", x, y),
+            AddReg { x, y } => format!("ADD V{:X}, V{:X}", x, y),
+            Sub { x, y } => format!("SUB V{:X}, V{:X}", x, y),
+            Shr { x, y } => format!("SHR V{:X}, V{:X}", x, y),
+            SubN { x, y } => format!("SUBN V{:X}, V{:X}", x, y),
+            Shl { x, y } => format!("SHL V{:X}, V{:X}", x, y),
+            SkipNeReg { x, y } => format!("SNE V{:X}, V{:X}", x, y),
+            LoadI { nnn } => format!("LD I, {}", addr(nnn)),
+            JumpV0 { nnn } => format!("JP V0, {}", addr(nnn)),
+            Random { x, nn } => format!("RND V{:X}, 0x{:02X}", x, nn),
+            Draw { x, y, n } => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+            SkipKey { x } => format!("SKP V{:X}", x),
+            SkipNotKey { x } => format!("SKNP V{:X}", x),
+            LoadILong { nnnn } => format!("LD I, {}", addr(nnnn)),
+            Plane { n } => format!("PLANE {}", n),
+            Audio => "AUDIO".to_string(),
+            LoadDelay { x } => format!("LD V{:X}, DT", x),
+            WaitKey { x } => format!("LD V{:X}, K", x),
+            SetDelay { x } => format!("LD DT, V{:X}", x),
+            SetSound { x } => format!("LD ST, V{:X}", x),
+            AddI { x } => format!("ADD I, V{:X}", x),
+            Font { x } => format!("LD F, V{:X}", x),
+            BigFont { x } => format!("LD HF, V{:X}", x),
+            Bcd { x } => format!("LD B, V{:X}", x),
+            Pitch { x } => format!("PITCH V{:X}", x),
+            Store { x } => format!("LD [I], V{:X}", x),
+            Load { x } => format!("LD V{:X}, [I]", x),
+            SaveFlags { x } => format!("LD R, V{:X}", x),
+            LoadFlags { x } => format!("LD V{:X}, R", x),
+            Unknown { opcode } => format!("DW 0x{:04X}", opcode),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.format_with(&|a| format!("0x{:03X}", a)))
+    }
+}
+
+/// An element of a disassembled rom
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Item {
+    Code {
+        addr: u16,
+        bytes: Vec<u8>,
+        instruction: Instruction,
+    },
+    /// Bytes that are not reached as code
+    Data { addr: u16, bytes: Vec<u8> },
+}
+
+pub struct Disassembly {
+    pub items: Vec<Item>,
+    /// Names given to the addresses used by jumps, calls and I loads
+    pub labels: BTreeMap<u16, String>,
+}
+
+/// Disassembles a rom loaded at `origin`. With `linear` every pair of bytes
+/// is decoded as an instruction, otherwise the code is found by following
+/// the control flow from `origin`, and the bytes never reached are data
+pub fn disassemble(rom: &[u8], origin: u16, linear: bool) -> Disassembly {
+    let mut code_start = vec![false; rom.len()];
+    let mut is_code = vec![false; rom.len()];
+    let mut labels = BTreeMap::new();
+    let offset = |addr: u16| -> Option<usize> {
+        let off = (addr as usize).checked_sub(origin as usize)?;
+        if off < rom.len() {
+            Some(off)
+        } else {
+            None
+        }
+    };
+
+    let mut pending = vec![origin];
+    while let Some(addr) = pending.pop() {
+        let off = match offset(addr) {
+            Some(off) => off,
+            None => continue,
+        };
+        if is_code[off] {
+            continue;
+        }
+        let (instruction, len) = match decode_at(rom, off) {
+            Some(decoded) => decoded,
+            None => continue,
+        };
+        let next = addr.wrapping_add(len as u16);
+        if linear {
+            code_start[off] = true;
+            is_code[off..off + len].iter_mut().for_each(|b| *b = true);
+            pending.push(next);
+            continue;
+        }
+        // an unknown opcode means the path went into data
+        if let Instruction::Unknown { .. } = instruction {
+            continue;
+        }
+        code_start[off] = true;
+        is_code[off..off + len].iter_mut().for_each(|b| *b = true);
+        match instruction {
+            Instruction::Jump { nnn } => {
+                labels
+                    .entry(nnn)
+                    .or_insert_with(|| format!("label_{:03X}", nnn));
+                pending.push(nnn);
+            }
+            Instruction::Call { nnn } => {
+                labels.insert(nnn, format!("sub_{:03X}", nnn));
+                pending.push(next);
+                pending.push(nnn);
+            }
+            Instruction::LoadI { nnn } => {
+                labels
+                    .entry(nnn)
+                    .or_insert_with(|| format!("data_{:03X}", nnn));
+                pending.push(next);
+            }
+            // the target of BNNN depends on the registers, it can't be followed
+            Instruction::Ret | Instruction::Exit | Instruction::JumpV0 { .. } => {}
+            _ if instruction.is_skip() => {
+                if let Some((_, skipped)) = offset(next).and_then(|o| decode_at(rom, o)) {
+                    pending.push(next.wrapping_add(skipped as u16));
+                }
+                pending.push(next);
+            }
+            _ => pending.push(next),
+        }
+    }
+    // a call can name an address already labelled by a jump
+    for (addr, label) in labels.iter_mut() {
+        if offset(*addr).is_some_and(|o| !code_start[o]) && label.starts_with("label_") {
+            *label = format!("data_{:03X}", addr);
+        }
+    }
+
+    let mut items = Vec::new();
+    let mut off = 0;
+    while off < rom.len() {
+        let addr = origin.wrapping_add(off as u16);
+        if code_start[off] {
+            let (instruction, len) = decode_at(rom, off).unwrap();
+            items.push(Item::Code {
+                addr,
+                bytes: rom[off..off + len].to_vec(),
+                instruction,
+            });
+            off += len;
+            continue;
+        }
+        // data runs stop at code and labels, 8 bytes per line
+        let start = off;
+        off += 1;
+        while off < rom.len()
+            && off - start < 8
+            && !code_start[off]
+            && !labels.contains_key(&origin.wrapping_add(off as u16))
+        {
+            off += 1;
+        }
+        items.push(Item::Data {
+            addr,
+            bytes: rom[start..off].to_vec(),
+        });
+    }
+    Disassembly { items, labels }
+}
+
+impl Disassembly {
+    /// Listing with one line per item: address, bytes and mnemonic, with
+    /// the labels before the addresses they name
+    pub fn listing(&self) -> String {
+        let name = |a: u16| -> String {
+            match self.labels.get(&a) {
+                Some(label) => label.clone(),
+                None => format!("0x{:03X}", a),
+            }
+        };
+        let mut s = String::new();
+        for item in &self.items {
+            let (addr, bytes, text) = match item {
+                Item::Code {
+                    addr,
+                    bytes,
+                    instruction,
+                } => (*addr, bytes, instruction.format_with(&name)),
+                Item::Data { addr, bytes } => {
+                    let values: Vec<String> =
+                        bytes.iter().map(|b| format!("0x{:02X}", b)).collect();
+                    (*addr, bytes, format!("DB {}", values.join(", ")))
+                }
+            };
+            if let Some(label) = self.labels.get(&addr) {
+                s.push_str(&format!("{}:\n", label));
+            }
+            let hex: String = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            s.push_str(&format!("0x{:03X}  {:<16}  {}\n", addr, hex, text));
+        }
+        s
+    }
+}
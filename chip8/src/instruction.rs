@@ -0,0 +1,337 @@
+//! Decoding of opcodes into typed instructions and back, shared by the
+//! interpreter, the disassembler and the assembler.
+
+use crate::Platform;
+
+/// A CHIP-8, SUPER-CHIP or XO-CHIP instruction. `x` and `y` are register
+/// numbers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// 0NNN Calls machine code at NNN
+    Sys { nnn: u16 },
+    /// 00E0
+    Cls,
+    /// 00EE
+    Ret,
+    /// 00CN
+    ScrollDown { n: u8 },
+    /// 00DN
+    ScrollUp { n: u8 },
+    /// 00FB
+    ScrollRight,
+    /// 00FC
+    ScrollLeft,
+    /// 00FD
+    Exit,
+    /// 00FE
+    Lores,
+    /// 00FF
+    Hires,
+    /// 1NNN
+    Jump { nnn: u16 },
+    /// 2NNN
+    Call { nnn: u16 },
+    /// 3XNN
+    SkipEqByte { x: u8, nn: u8 },
+    /// 4XNN
+    SkipNeByte { x: u8, nn: u8 },
+    /// 5XY0
+    SkipEqReg { x: u8, y: u8 },
+    /// 5XY2
+    SaveRange { x: u8, y: u8 },
+    /// 5XY3
+    LoadRange { x: u8, y: u8 },
+    /// 6XNN
+    LoadByte { x: u8, nn: u8 },
+    /// 7XNN
+    AddByte { x: u8, nn: u8 },
+    /// 8XY0
+    LoadReg { x: u8, y: u8 },
+    /// 8XY1
+    Or { x: u8, y: u8 },
+    /// 8XY2
+    And { x: u8, y: u8 },
+    /// 8XY3
+    Xor { x: u8, y: u8 },
+    /// 8XY4
+    AddReg { x: u8, y: u8 },
+    /// 8XY5
+    Sub { x: u8, y: u8 },
+    /// 8XY6
+    Shr { x: u8, y: u8 },
+    /// 8XY7
+    SubN { x: u8, y: u8 },
+    /// 8XYE
+    Shl { x: u8, y: u8 },
+    /// 9XY0
+    SkipNeReg { x: u8, y: u8 },
+    /// ANNN
+    LoadI { nnn: u16 },
+    /// BNNN, read as BXNN with the jump quirk
+    JumpV0 { nnn: u16 },
+    /// CXNN
+    Random { x: u8, nn: u8 },
+    /// DXYN
+    Draw { x: u8, y: u8, n: u8 },
+    /// EX9E
+    SkipKey { x: u8 },
+    /// EXA1
+    SkipNotKey { x: u8 },
+    /// F000 NNNN, the address is in the two bytes after the opcode
+    LoadILong { nnnn: u16 },
+    /// FN01
+    Plane { n: u8 },
+    /// F002
+    Audio,
+    /// FX07
+    LoadDelay { x: u8 },
+    /// FX0A
+    WaitKey { x: u8 },
+    /// FX15
+    SetDelay { x: u8 },
+    /// FX18
+    SetSound { x: u8 },
+    /// FX1E
+    AddI { x: u8 },
+    /// FX29
+    Font { x: u8 },
+    /// FX30
+    BigFont { x: u8 },
+    /// FX33
+    Bcd { x: u8 },
+    /// FX3A
+    Pitch { x: u8 },
+    /// FX55
+    Store { x: u8 },
+    /// FX65
+    Load { x: u8 },
+    /// FX75
+    SaveFlags { x: u8 },
+    /// FX85
+    LoadFlags { x: u8 },
+    /// Any opcode not listed above
+    Unknown { opcode: u16 },
+}
+
+/// Decodes a single opcode. F000 is decoded as `LoadILong` with a zero
+/// address, as the address is in the next opcode, see `decode_at`
+pub fn decode(opcode: u16) -> Instruction {
+    use Instruction::*;
+    let x = ((opcode & 0x0F00) >> 8) as u8;
+    let y = ((opcode & 0x00F0) >> 4) as u8;
+    let n = (opcode & 0x000F) as u8;
+    let nn = (opcode & 0x00FF) as u8;
+    let nnn = opcode & 0x0FFF;
+    match opcode & 0xF000 {
+        0x0000 => match opcode {
+            0x00E0 => Cls,
+            0x00EE => Ret,
+            0x00C0..=0x00CF => ScrollDown { n },
+            0x00D0..=0x00DF => ScrollUp { n },
+            0x00FB => ScrollRight,
+            0x00FC => ScrollLeft,
+            0x00FD => Exit,
+            0x00FE => Lores,
+            0x00FF => Hires,
+            _ => Sys { nnn },
+        },
+        0x1000 => Jump { nnn },
+        0x2000 => Call { nnn },
+        0x3000 => SkipEqByte { x, nn },
+        0x4000 => SkipNeByte { x, nn },
+        0x5000 => match n {
+            0x0 => SkipEqReg { x, y },
+            0x2 => SaveRange { x, y },
+            0x3 => LoadRange { x, y },
+            _ => Unknown { opcode },
+        },
+        0x6000 => LoadByte { x, nn },
+        0x7000 => AddByte { x, nn },
+        0x8000 => match n {
+            0x0 => LoadReg { x, y },
+            0x1 => Or { x, y },
+            0x2 => And { x, y },
+            0x3 => Xor { x, y },
+            0x4 => AddReg { x, y },
+            0x5 => Sub { x, y },
+            0x6 => Shr { x, y },
+            0x7 => SubN { x, y },
+            0xE => Shl { x, y },
+            _ => Unknown { opcode },
+        },
+        0x9000 if n == 0 => SkipNeReg { x, y },
+        0xA000 => LoadI { nnn },
+        0xB000 => JumpV0 { nnn },
+        0xC000 => Random { x, nn },
+        0xD000 => Draw { x, y, n },
+        0xE000 => match nn {
+            0x9E => SkipKey { x },
+            0xA1 => SkipNotKey { x },
+            _ => Unknown { opcode },
+        },
+        0xF000 => match nn {
+            0x00 if x == 0 => LoadILong { nnnn: 0 },
+            0x01 => Plane { n: x },
+            0x02 if x == 0 => Audio,
+            0x07 => LoadDelay { x },
+            0x0A => WaitKey { x },
+            0x15 => SetDelay { x },
+            0x18 => SetSound { x },
+            0x1E => AddI { x },
+            0x29 => Font { x },
+            0x30 => BigFont { x },
+            0x33 => Bcd { x },
+            0x3A => Pitch { x },
+            0x55 => Store { x },
+            0x65 => Load { x },
+            0x75 => SaveFlags { x },
+            0x85 => LoadFlags { x },
+            _ => Unknown { opcode },
+        },
+        _ => Unknown { opcode },
+    }
+}
+
+/// Decodes the instruction at `addr` of `bytes`, returning it with its
+/// length in bytes. Returns None when the instruction goes past the end
+pub fn decode_at(bytes: &[u8], addr: usize) -> Option<(Instruction, usize)> {
+    if addr + 2 > bytes.len() {
+        return None;
+    }
+    let opcode = (bytes[addr] as u16) << 8 | bytes[addr + 1] as u16;
+    match decode(opcode) {
+        Instruction::LoadILong { .. } => {
+            if addr + 4 > bytes.len() {
+                return None;
+            }
+            let nnnn = (bytes[addr + 2] as u16) << 8 | bytes[addr + 3] as u16;
+            Some((Instruction::LoadILong { nnnn }, 4))
+        }
+        instruction => Some((instruction, 2)),
+    }
+}
+
+impl Instruction {
+    /// Length of the instruction in bytes
+    pub fn size(&self) -> usize {
+        match self {
+            Instruction::LoadILong { .. } => 4,
+            _ => 2,
+        }
+    }
+    /// Whether the instruction conditionally skips the next one
+    pub fn is_skip(&self) -> bool {
+        use Instruction::*;
+        matches!(
+            self,
+            SkipEqByte { .. }
+                | SkipNeByte { .. }
+                | SkipEqReg { .. }
+                | SkipNeReg { .. }
+                | SkipKey { .. }
+                | SkipNotKey { .. }
+        )
+    }
+    /// Whether the platform has the instruction. `Sys` and `Unknown` are
+    /// never supported, as the machine code of the original interpreter
+    /// can't run
+    pub fn is_supported(&self, platform: Platform) -> bool {
+        use Instruction::*;
+        match self {
+            Sys { .. } | Unknown { .. } => false,
+            ScrollDown { .. } | ScrollRight | ScrollLeft | Exit | Lores | Hires => {
+                platform != Platform::Chip8
+            }
+            BigFont { .. } | SaveFlags { .. } | LoadFlags { .. } => platform != Platform::Chip8,
+            ScrollUp { .. }
+            | SaveRange { .. }
+            | LoadRange { .. }
+            | LoadILong { .. }
+            | Plane { .. }
+            | Audio
+            | Pitch { .. } => platform == Platform::XoChip,
+            _ => true,
+        }
+    }
+    /// Whether the fields fit in the opcode, which holds for all decoded
+    /// instructions but not for any built by hand: registers and `n` up to
+    /// 15, addresses up to 0xFFF, and no `Sys` or `Unknown` opcode that
+    /// decodes to another instruction
+    pub fn is_valid(&self) -> bool {
+        match (self, decode(self.encode())) {
+            (Instruction::LoadILong { .. }, Instruction::LoadILong { .. }) => true,
+            (instruction, decoded) => *instruction == decoded,
+        }
+    }
+    /// Encodes the instruction back into its opcode, the inverse of
+    /// `decode` for valid instructions, see `is_valid`. The address of
+    /// `LoadILong` is not part of it, see `bytes`
+    pub fn encode(&self) -> u16 {
+        use Instruction::*;
+        let xy = |op: u16, x: u8, y: u8| op | (x as u16) << 8 | (y as u16) << 4;
+        let xnn = |op: u16, x: u8, nn: u8| op | (x as u16) << 8 | nn as u16;
+        let fx = |x: u8, nn: u16| 0xF000 | (x as u16) << 8 | nn;
+        match *self {
+            Sys { nnn } => nnn,
+            Cls => 0x00E0,
+            Ret => 0x00EE,
+            ScrollDown { n } => 0x00C0 | n as u16,
+            ScrollUp { n } => 0x00D0 | n as u16,
+            ScrollRight => 0x00FB,
+            ScrollLeft => 0x00FC,
+            Exit => 0x00FD,
+            Lores => 0x00FE,
+            Hires => 0x00FF,
+            Jump { nnn } => 0x1000 | nnn,
+            Call { nnn } => 0x2000 | nnn,
+            SkipEqByte { x, nn } => xnn(0x3000, x, nn),
+            SkipNeByte { x, nn } => xnn(0x4000, x, nn),
+            SkipEqReg { x, y } => xy(0x5000, x, y),
+            SaveRange { x, y } => xy(0x5002, x, y),
+            LoadRange { x, y } => xy(0x5003, x, y),
+            LoadByte { x, nn } => xnn(0x6000, x, nn),
+            AddByte { x, nn } => xnn(0x7000, x, nn),
+            LoadReg { x, y } => xy(0x8000, x, y),
+            Or { x, y } => xy(0x8001, x, y),
+            And { x, y } => xy(0x8002, x, y),
+            Xor { x, y } => xy(0x8003, x, y),
+            AddReg { x, y } => xy(0x8004, x, y),
+            Sub { x, y } => xy(0x8005, x, y),
+            Shr { x, y } => xy(0x8006, x, y),
+            SubN { x, y } => xy(0x8007, x, y),
+            Shl { x, y } => xy(0x800E, x, y),
+            SkipNeReg { x, y } => xy(0x9000, x, y),
+            LoadI { nnn } => 0xA000 | nnn,
+            JumpV0 { nnn } => 0xB000 | nnn,
+            Random { x, nn } => xnn(0xC000, x, nn),
+            Draw { x, y, n } => xy(0xD000, x, y) | n as u16,
+            SkipKey { x } => xnn(0xE000, x, 0x9E),
+            SkipNotKey { x } => xnn(0xE000, x, 0xA1),
+            LoadILong { .. } => 0xF000,
+            Plane { n } => fx(n, 0x01),
+            Audio => 0xF002,
+            LoadDelay { x } => fx(x, 0x07),
+            WaitKey { x } => fx(x, 0x0A),
+            SetDelay { x } => fx(x, 0x15),
+            SetSound { x } => fx(x, 0x18),
+            AddI { x } => fx(x, 0x1E),
+            Font { x } => fx(x, 0x29),
+            BigFont { x } => fx(x, 0x30),
+            Bcd { x } => fx(x, 0x33),
+            Pitch { x } => fx(x, 0x3A),
+            Store { x } => fx(x, 0x55),
+            Load { x } => fx(x, 0x65),
+            SaveFlags { x } => fx(x, 0x75),
+            LoadFlags { x } => fx(x, 0x85),
+            Unknown { opcode } => opcode,
+        }
+    }
+    /// Bytes of the instruction in memory, `size()` long
+    pub fn bytes(&self) -> Vec<u8> {
+        let mut bytes = self.encode().to_be_bytes().to_vec();
+        if let Instruction::LoadILong { nnnn } = self {
+            bytes.extend_from_slice(&nnnn.to_be_bytes());
+        }
+        bytes
+    }
+}
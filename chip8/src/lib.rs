@@ -1,26 +1,161 @@
-use rand::Rng;
+use std::error::Error;
+use std::fmt;
 use std::fs;
-use std::io::Read;
+use std::io;
+use std::path::Path;
 
-const w: usize = 64;
-const h: usize = 32;
+pub mod asm;
+mod backend;
+pub mod disasm;
+pub mod instruction;
+pub mod movie;
+mod platform;
+mod quirks;
+mod rewind;
+mod rng;
+mod setup;
+mod state;
+use backend::DecodeCache;
+pub use backend::{Backend, UnknownBackend};
+use instruction::decode;
+pub use instruction::Instruction;
+pub use platform::{Platform, UnknownPlatform};
+pub use quirks::{Quirks, UnknownQuirks};
+pub use rewind::Rewind;
+use rng::Rng;
+pub use state::StateError;
 
+const W: usize = 64;
+const H: usize = 32;
+const HIRES_W: usize = 128;
+const HIRES_H: usize = 64;
+const BIG_FONT_START: usize = 0x50;
+/// XO-CHIP audio pattern until F002 loads one, a square wave as in Octo
+const SQUARE_WAVE: [u8; 16] = [
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+/// Address where roms are loaded
+pub const PROGRAM_START: usize = 0x200;
+
+#[derive(Debug)]
+pub enum LoadError {
+    /// The ROM file could not be read
+    Io(io::Error),
+    /// The ROM does not contain any byte
+    EmptyRom,
+    /// The ROM does not fit in the memory available from 0x200 onwards
+    RomTooLarge { size: usize, max: usize },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(err) => write!(f, "can not read rom file: {}", err),
+            LoadError::EmptyRom => write!(f, "rom is empty"),
+            LoadError::RomTooLarge { size, max } => write!(
+                f,
+                "rom is too large: {} bytes, but only {} fit in memory",
+                size, max
+            ),
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoadError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(err: io::Error) -> LoadError {
+        LoadError::Io(err)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuError {
+    /// The opcode at `pc` is not a valid instruction
+    UnknownOpcode { pc: u16, opcode: u16 },
+    /// A subroutine call was made with the 16 stack levels already in use
+    StackOverflow { pc: u16 },
+    /// A return was made with an empty stack
+    StackUnderflow { pc: u16 },
+    /// An instruction tried to access an address outside of memory
+    MemoryOutOfBounds { addr: usize },
+    /// An instruction given to `execute` has fields that don't fit in its
+    /// opcode
+    InvalidInstruction { pc: u16, instruction: Instruction },
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CpuError::UnknownOpcode { pc, opcode } => {
+                write!(f, "unknown opcode {:04x} at {:04x}", opcode, pc)
+            }
+            CpuError::StackOverflow { pc } => write!(f, "stack overflow at {:04x}", pc),
+            CpuError::StackUnderflow { pc } => write!(f, "stack underflow at {:04x}", pc),
+            CpuError::MemoryOutOfBounds { addr } => {
+                write!(f, "memory access out of bounds at {:04x}", addr)
+            }
+            CpuError::InvalidInstruction { pc, instruction } => {
+                write!(f, "invalid instruction {:?} at {:04x}", instruction, pc)
+            }
+        }
+    }
+}
+
+impl Error for CpuError {}
+
+/// Result of a successfully executed `step`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// The instruction was executed and `pc` moved on
+    Executed,
+    /// FX0A is blocking until a key is pressed, `pc` did not move
+    WaitingForKey,
+    /// 00FD asked the interpreter to exit, `pc` did not move
+    Exit,
+}
+
+#[derive(Clone)]
 pub struct Chip8 {
     opcode: u16,
-    memory: [u8; 4096],
+    memory: Vec<u8>,
     v: [u8; 16],
     index: u16,
     pc: u16,
-    pub gfx: [u8; w * h],
+    /// Pixels of the display, row by row, `width() * height()` long. Each
+    /// pixel holds one bit per plane, so it is 0 or 1 unless XO-CHIP draws
+    /// on the second plane, giving values up to 3
+    pub gfx: Vec<u8>,
+    hires: bool,
+    planes: u8,
     delay_timer: u8,
     sound_timer: u8,
     stack: [u16; 16],
     sp: usize,
     pub key: [u8; 16],
     pub draw_flag: bool,
+    quirks: Quirks,
+    platform: Platform,
+    rpl: [u8; 16],
+    audio_pattern: [u8; 16],
+    pitch: u8,
+    /// FNV-1a hash of the loaded rom, to match save states with their rom
+    rom_hash: u64,
+    /// Seed `rng` started from, CXNN draws its numbers from `rng`
+    seed: u64,
+    rng: Rng,
+    backend: Backend,
+    cache: DecodeCache,
 }
 
-const font_set: [u8; 80] = [
+const FONT_SET: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
     0x20, 0x60, 0x20, 0x20, 0x70, // 1
     0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
@@ -39,358 +174,839 @@ const font_set: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
+// SUPER-CHIP 8x10 font, used by FX30. The A-F digits come from XO-CHIP
+const BIG_FONT_SET: [u8; 160] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+/// 64-bit FNV-1a hash, http://www.isthe.com/chongo/tech/comp/fnv/
+pub fn rom_hash(rom: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in rom {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Reads and decodes the instruction at `pc`
+fn fetch(memory: &[u8], pc: usize, platform: Platform) -> Result<Instruction, CpuError> {
+    let read = |addr: usize| match memory.get(addr) {
+        Some(b) => Ok(*b as u16),
+        None => Err(CpuError::MemoryOutOfBounds { addr }),
+    };
+    match decode(read(pc)? << 8 | read(pc + 1)?) {
+        // the address of F000 NNNN is in the next two bytes
+        Instruction::LoadILong { .. } if platform == Platform::XoChip => {
+            Ok(Instruction::LoadILong {
+                nnnn: read(pc + 2)? << 8 | read(pc + 3)?,
+            })
+        }
+        instruction => Ok(instruction),
+    }
+}
+
+impl Default for Chip8 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Chip8 {
     pub fn new() -> Chip8 {
+        Chip8::with_quirks(Quirks::default())
+    }
+    /// Creates a CHIP-8 machine that follows the given interpreter quirks
+    pub fn with_quirks(quirks: Quirks) -> Chip8 {
+        Chip8::with_platform(Platform::Chip8, quirks)
+    }
+    /// Creates a machine for the given platform, `platform.quirks()` gives
+    /// the quirks usually expected on it
+    pub fn with_platform(platform: Platform, quirks: Quirks) -> Chip8 {
         let mut c = Chip8 {
             opcode: 0,
-            memory: [0; 4096],
+            memory: vec![0; platform.memory_size()],
             v: [0; 16],
             index: 0,
             pc: 0x200,
-            gfx: [0; w * h],
+            gfx: vec![0; W * H],
+            hires: false,
+            planes: 1,
             delay_timer: 0,
             sound_timer: 0,
             stack: [0; 16],
             sp: 0,
             key: [0; 16],
             draw_flag: false,
+            quirks,
+            platform,
+            rpl: [0; 16],
+            audio_pattern: SQUARE_WAVE,
+            pitch: 64,
+            rom_hash: 0,
+            seed: 0,
+            rng: Rng::new(0),
+            backend: Backend::Interpreter,
+            cache: DecodeCache::default(),
         };
+        let seed = Rng::random_seed();
+        c.seed = seed;
+        c.rng = Rng::new(seed);
 
-        for i in 0..font_set.len() {
-            c.memory[i] = font_set[i];
-        }
+        c.memory[..FONT_SET.len()].copy_from_slice(&FONT_SET);
+        c.memory[BIG_FONT_START..BIG_FONT_START + BIG_FONT_SET.len()]
+            .copy_from_slice(&BIG_FONT_SET);
         c
     }
-    pub fn load_game(&mut self, filepath: &str) {
-        let mut f = fs::File::open(filepath).expect("can not load rom file");
-        let metadata = fs::metadata(filepath).expect("unable to read metadata");
-        let mut b = vec![0; metadata.len() as usize];
-        f.read(&mut b).expect("buffer overflow");
-        for i in 0..b.len() {
-            self.memory[512 + i] = b[i];
-        }
-    }
-    pub fn emulate_cycle(&mut self) {
-        self.opcode = ((self.memory[self.pc as usize] as u16) << 8)
-            | (self.memory[(self.pc + 1) as usize] as u16);
-        let x: usize = ((self.opcode & 0x0F00) >> 8) as usize;
-        let y: usize = ((self.opcode & 0x00F0) >> 4) as usize;
-        let nn: u8 = (self.opcode & 0x00FF) as u8;
-        let nnn: u16 = (self.opcode & 0x0FFF) as u16;
-        // println!("{:x} {:?}", self.opcode, self.pc);
-
-        // Decode Opcode
-        // https://en.wikipedia.org/wiki/CHIP-8#Opcode_table
-        match self.opcode & 0xF000 {
-            0x0000 => {
-                match self.opcode & 0x000F {
-                    0x0000 => {
-                        // 00E0 Clear screen
-                        for i in 0..self.gfx.len() {
-                            self.gfx[i] = 0;
-                        }
-                        self.pc += 2;
-                        self.draw_flag = true;
+    /// Seeds the random numbers of CXNN, which otherwise come from a seed
+    /// taken from the clock. Runs with the same seed, rom and input
+    /// produce the same output.
+    pub fn with_rng(mut self, seed: u64) -> Chip8 {
+        self.seed = seed;
+        self.rng = Rng::new(seed);
+        self
+    }
+    /// Selects how instructions are decoded, see `Backend`
+    pub fn with_backend(mut self, backend: Backend) -> Chip8 {
+        self.backend = backend;
+        self.cache = match backend {
+            Backend::Interpreter => DecodeCache::default(),
+            Backend::Cached => DecodeCache::new(self.memory.len()),
+        };
+        self
+    }
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+    /// Seed of the random numbers, see `with_rng`
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+    /// The opcode of the last instruction executed
+    pub fn opcode(&self) -> u16 {
+        self.opcode
+    }
+    /// The whole address space, with the fonts and the rom at 0x200
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+    /// The I register
+    pub fn index(&self) -> u16 {
+        self.index
+    }
+    /// The V0 to VF registers
+    pub fn v(&self) -> &[u8; 16] {
+        &self.v
+    }
+    /// Return addresses of the subroutine calls, only the first `sp()` are
+    /// in use
+    pub fn stack(&self) -> &[u16; 16] {
+        &self.stack
+    }
+    pub fn sp(&self) -> usize {
+        self.sp
+    }
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+    /// Width in pixels of the display in its current mode
+    pub fn width(&self) -> usize {
+        if self.hires {
+            HIRES_W
+        } else {
+            W
+        }
+    }
+    /// Height in pixels of the display in its current mode
+    pub fn height(&self) -> usize {
+        if self.hires {
+            HIRES_H
+        } else {
+            H
+        }
+    }
+    /// XO-CHIP planes drawn, cleared and scrolled, as a bit mask
+    pub fn planes(&self) -> u8 {
+        self.planes
+    }
+    /// Whether the buzzer is sounding, which is while the sound timer is
+    /// non-zero
+    pub fn is_sound_active(&self) -> bool {
+        self.sound_timer > 0
+    }
+    /// XO-CHIP 1-bit audio pattern, set with F002, a square wave before
+    pub fn audio_pattern(&self) -> &[u8; 16] {
+        &self.audio_pattern
+    }
+    /// XO-CHIP playback rate of the audio pattern, set with FX3A. The
+    /// pattern is played at 4000 * 2 ^ ((pitch - 64) / 48) bits per second
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+    /// Hash of the loaded rom, 0 if none was loaded
+    pub fn rom_hash(&self) -> u64 {
+        self.rom_hash
+    }
+    /// Copies the given rom into memory, starting at 0x200
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), LoadError> {
+        if rom.is_empty() {
+            return Err(LoadError::EmptyRom);
+        }
+        let max = self.memory.len() - PROGRAM_START;
+        if rom.len() > max {
+            return Err(LoadError::RomTooLarge {
+                size: rom.len(),
+                max,
+            });
+        }
+        self.memory[PROGRAM_START..PROGRAM_START + rom.len()].copy_from_slice(rom);
+        self.cache.invalidate(PROGRAM_START, rom.len());
+        self.rom_hash = rom_hash(rom);
+        Ok(())
+    }
+    /// Reads the rom file at the given path and loads it into memory
+    pub fn load_game<P: AsRef<Path>>(&mut self, filepath: P) -> Result<(), LoadError> {
+        let rom = fs::read(filepath)?;
+        self.load_rom(&rom)
+    }
+    /// Checks that `len` bytes starting at `addr` are inside memory
+    fn check_mem(&self, addr: usize, len: usize) -> Result<(), CpuError> {
+        if addr + len > self.memory.len() {
+            return Err(CpuError::MemoryOutOfBounds {
+                addr: self.memory.len().max(addr),
+            });
+        }
+        Ok(())
+    }
+    fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.gfx = vec![0; self.width() * self.height()];
+        self.draw_flag = true;
+    }
+    /// Moves the selected planes of the display by (dx, dy) pixels, filling
+    /// the uncovered area with blank pixels
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let (w, h) = (self.width() as isize, self.height() as isize);
+        let old = self.gfx.clone();
+        for y in 0..h {
+            for x in 0..w {
+                let (sx, sy) = (x - dx, y - dy);
+                let moved = if sx >= 0 && sx < w && sy >= 0 && sy < h {
+                    old[(sy * w + sx) as usize] & self.planes
+                } else {
+                    0
+                };
+                let pos = (y * w + x) as usize;
+                self.gfx[pos] = (self.gfx[pos] & !self.planes) | moved;
+            }
+        }
+        self.draw_flag = true;
+    }
+    /// Address `len` bytes after `pc`. Fails past the 64 KiB addressed by
+    /// the 16 bits of `pc`, the end of XO-CHIP memory
+    fn next_pc(&self, len: u16) -> Result<u16, CpuError> {
+        self.pc.checked_add(len).ok_or(CpuError::MemoryOutOfBounds {
+            addr: self.pc as usize + len as usize,
+        })
+    }
+    /// Length of the instruction after the current one, which is skipped by
+    /// the conditional skips. On XO-CHIP, F000 NNNN takes 4 bytes
+    fn skip_len(&self) -> u16 {
+        let next = self.pc as usize + 2;
+        if self.platform == Platform::XoChip
+            && self.memory.get(next) == Some(&0xF0)
+            && self.memory.get(next + 1) == Some(&0x00)
+        {
+            4
+        } else {
+            2
+        }
+    }
+    /// XORs a sprite read from I onto the display at (vx, vy) and sets VF on
+    /// collision. Sprites are 8 pixels wide, or 16 when `wide` is set, in
+    /// which case each row is read from two bytes. When several planes are
+    /// selected, the sprite data of each plane follows the previous one
+    fn draw_sprite(&mut self, vx: u8, vy: u8, rows: usize, wide: bool) -> Result<(), CpuError> {
+        let (w, h) = (self.width(), self.height());
+        let cols = if wide { 16 } else { 8 };
+        let row_bytes = cols / 8;
+        let sprite_len = rows * row_bytes;
+        self.check_mem(
+            self.index as usize,
+            sprite_len * self.planes.count_ones() as usize,
+        )?;
+        let x0 = vx as usize % w;
+        let y0 = vy as usize % h;
+        self.v[0xF] = 0;
+        let mut addr = self.index as usize;
+        for plane in 0..2 {
+            let bit = 1 << plane;
+            if self.planes & bit == 0 {
+                continue;
+            }
+            for yline in 0..rows {
+                let py = y0 + yline;
+                if py >= h && self.quirks.clip_sprites {
+                    break;
+                }
+                let row = addr + yline * row_bytes;
+                let pixels = if wide {
+                    (self.memory[row] as u16) << 8 | self.memory[row + 1] as u16
+                } else {
+                    (self.memory[row] as u16) << 8
+                };
+                for xline in 0..cols {
+                    let px = x0 + xline;
+                    if px >= w && self.quirks.clip_sprites {
+                        break;
                     }
-                    0x000E => {
-                        // 00EE Returns from a subroutine
-                        self.sp -= 1;
-                        self.pc = self.stack[self.sp];
-                        self.pc += 2;
+                    if (pixels & (0x8000 >> xline)) != 0 {
+                        let pos = (px % w) + (py % h) * w;
+                        if self.gfx[pos] & bit != 0 {
+                            self.v[0xF] = 1;
+                        }
+                        self.gfx[pos] ^= bit;
                     }
-                    _ => println!("Unknown opcode: {:x}", self.opcode),
                 }
             }
-            0x1000 => {
+            addr += sprite_len;
+        }
+        self.draw_flag = true;
+        Ok(())
+    }
+    /// Runs `ipf` instructions and then ticks the timers once. Calling it at
+    /// 60 Hz gives a CPU speed of `ipf * 60` instructions per second, while
+    /// the timers always count down at 60 Hz. The frame ends early when FX0A
+    /// is waiting for a key or when 00FD exits.
+    pub fn run_frame(&mut self, ipf: usize) -> Result<StepOutcome, CpuError> {
+        let mut outcome = StepOutcome::Executed;
+        for _ in 0..ipf {
+            outcome = self.step()?;
+            if outcome != StepOutcome::Executed {
+                break;
+            }
+        }
+        self.tick_timers();
+        Ok(outcome)
+    }
+    /// Decrements the delay and sound timers, must be called at 60 Hz
+    pub fn tick_timers(&mut self) {
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
+        }
+    }
+    /// Fetches, decodes and executes one instruction. The timers are not
+    /// updated, see `tick_timers`. On error the machine is left as it was
+    /// before the instruction, so the host can decide whether to halt or
+    /// inspect it.
+    pub fn step(&mut self) -> Result<StepOutcome, CpuError> {
+        let pc = self.pc as usize;
+        if let Some((instruction, opcode)) = self.cache.get(pc) {
+            return self.run(instruction, opcode);
+        }
+        if self.backend == Backend::Cached {
+            let (memory, platform) = (&self.memory, self.platform);
+            self.cache.decode(pc, |addr| {
+                fetch(memory, addr, platform)
+                    .ok()
+                    .filter(|instruction| instruction.is_supported(platform))
+            });
+            if let Some((instruction, opcode)) = self.cache.get(pc) {
+                return self.run(instruction, opcode);
+            }
+        }
+        let instruction = fetch(&self.memory, pc, self.platform)?;
+        let opcode = self.check(instruction)?;
+        self.run(instruction, opcode)
+    }
+    /// Executes a decoded instruction as if it was read at `pc`, which then
+    /// moves past it, see `step`. Instructions the platform doesn't have
+    /// fail with `UnknownOpcode`, and instructions with fields too large
+    /// for their opcode, such as a register above 15, with
+    /// `InvalidInstruction`
+    pub fn execute(&mut self, instruction: Instruction) -> Result<StepOutcome, CpuError> {
+        let opcode = self.check(instruction)?;
+        self.run(instruction, opcode)
+    }
+    /// Checks the instruction is valid and the platform has it, and gives
+    /// its opcode
+    fn check(&self, instruction: Instruction) -> Result<u16, CpuError> {
+        if !instruction.is_valid() {
+            return Err(CpuError::InvalidInstruction {
+                pc: self.pc,
+                instruction,
+            });
+        }
+        let opcode = instruction.encode();
+        if !instruction.is_supported(self.platform) {
+            return Err(CpuError::UnknownOpcode {
+                pc: self.pc,
+                opcode,
+            });
+        }
+        Ok(opcode)
+    }
+    /// Executes an instruction that passed `check`, and sets `opcode` to its
+    /// opcode when it succeeds
+    fn run(&mut self, instruction: Instruction, opcode: u16) -> Result<StepOutcome, CpuError> {
+        use Instruction::*;
+        let mut outcome = StepOutcome::Executed;
+        // address of the next instruction, checked before anything changes
+        // so that an error leaves the machine as it was
+        let next = match instruction {
+            Jump { .. } | Call { .. } | JumpV0 { .. } | Ret | Exit => self.pc,
+            _ => self.next_pc(instruction.size() as u16)?,
+        };
+
+        // https://en.wikipedia.org/wiki/CHIP-8#Opcode_table
+        match instruction {
+            Sys { .. } | Unknown { .. } => {
+                return Err(CpuError::UnknownOpcode {
+                    pc: self.pc,
+                    opcode,
+                })
+            }
+            Cls => {
+                // 00E0 Clear screen (the selected planes on XO-CHIP)
+                for i in 0..self.gfx.len() {
+                    self.gfx[i] &= !self.planes;
+                }
+                self.pc = next;
+                self.draw_flag = true;
+            }
+            Ret => {
+                // 00EE Returns from a subroutine
+                if self.sp == 0 {
+                    return Err(CpuError::StackUnderflow { pc: self.pc });
+                }
+                let ret = self.stack[self.sp - 1];
+                let pc = ret.checked_add(2).ok_or(CpuError::MemoryOutOfBounds {
+                    addr: ret as usize + 2,
+                })?;
+                self.sp -= 1;
+                self.pc = pc;
+            }
+            ScrollDown { n } => {
+                // 00CN Scrolls the display down by N pixels
+                self.scroll(0, n as isize);
+                self.pc = next;
+            }
+            ScrollUp { n } => {
+                // 00DN Scrolls the display up by N pixels
+                self.scroll(0, -(n as isize));
+                self.pc = next;
+            }
+            ScrollRight => {
+                // 00FB Scrolls the display right by 4 pixels
+                self.scroll(4, 0);
+                self.pc = next;
+            }
+            ScrollLeft => {
+                // 00FC Scrolls the display left by 4 pixels
+                self.scroll(-4, 0);
+                self.pc = next;
+            }
+            Exit => {
+                // 00FD Exits the interpreter
+                outcome = StepOutcome::Exit;
+            }
+            Lores => {
+                // 00FE Switches to the 64x32 low-res mode
+                self.set_hires(false);
+                self.pc = next;
+            }
+            Hires => {
+                // 00FF Switches to the 128x64 hi-res mode
+                self.set_hires(true);
+                self.pc = next;
+            }
+            Jump { nnn } => {
                 // 1NNN Jumps to address NNN
                 self.pc = nnn;
             }
-            0x2000 => {
+            Call { nnn } => {
                 // 2NNN Calls subroutine at NNN
+                if self.sp == self.stack.len() {
+                    return Err(CpuError::StackOverflow { pc: self.pc });
+                }
                 self.stack[self.sp] = self.pc;
                 self.sp += 1;
                 self.pc = nnn;
             }
-            0x3000 => {
+            SkipEqByte { x, nn } => {
                 // 3XNN Skips the next instruction if VX equals NN. (Usually
                 // the next instruction is a jump to skip a code block)
-                if self.v[x] == nn {
-                    self.pc += 2;
+                if self.v[x as usize] == nn {
+                    self.pc = self.next_pc(2 + self.skip_len())?;
+                } else {
+                    self.pc = next;
                 }
-                self.pc += 2;
             }
-            0x4000 => {
+            SkipNeByte { x, nn } => {
                 // 4XNN Skips the next instruction if VX doesn't equal NN.
                 // (Usually the next instruction is a jump to skip a code
                 // block)
-                if self.v[x] != nn {
-                    self.pc += 2;
+                if self.v[x as usize] != nn {
+                    self.pc = self.next_pc(2 + self.skip_len())?;
+                } else {
+                    self.pc = next;
                 }
-                self.pc += 2;
             }
-            0x5000 => {
+            SkipEqReg { x, y } => {
                 // 5XY0 Skips the next instruction if VX equals VY. (Usually
                 // the next instruction is a jump to skip a code block)
-                if self.v[x] == self.v[y] {
-                    self.pc += 2;
+                if self.v[x as usize] == self.v[y as usize] {
+                    self.pc = self.next_pc(2 + self.skip_len())?;
+                } else {
+                    self.pc = next;
                 }
-                self.pc += 2;
             }
-            0x6000 => {
+            SaveRange { x, y } => {
+                // 5XY2 Stores VX to VY in memory starting at I, in
+                // descending order if X > Y. I is left unmodified
+                let (x, y) = (x as usize, y as usize);
+                let n = x.abs_diff(y) + 1;
+                self.check_mem(self.index as usize, n)?;
+                for i in 0..n {
+                    let r = if x > y { x - i } else { x + i };
+                    self.memory[self.index as usize + i] = self.v[r];
+                }
+                self.cache.invalidate(self.index as usize, n);
+                self.pc = next;
+            }
+            LoadRange { x, y } => {
+                // 5XY3 Fills VX to VY from memory starting at I, in
+                // descending order if X > Y. I is left unmodified
+                let (x, y) = (x as usize, y as usize);
+                let n = x.abs_diff(y) + 1;
+                self.check_mem(self.index as usize, n)?;
+                for i in 0..n {
+                    let r = if x > y { x - i } else { x + i };
+                    self.v[r] = self.memory[self.index as usize + i];
+                }
+                self.pc = next;
+            }
+            LoadByte { x, nn } => {
                 // 6XNN Sets VX to NN
-                self.v[x] = nn;
-                self.pc += 2;
+                self.v[x as usize] = nn;
+                self.pc = next;
             }
-            0x7000 => {
+            AddByte { x, nn } => {
                 // 7XNN Adds NN to VX. (Carry flag is not changed)
-                self.v[x] = self.v[x].wrapping_add(nn);
-                self.pc += 2;
-            }
-            0x8000 => {
-                match self.opcode & 0x000F {
-                    0x0000 => {
-                        // 0x8XY0 Sets VX to the value of VY
-                        self.v[x] = self.v[y];
-                        self.pc += 2;
-                    }
-                    0x0001 => {
-                        // 0x8XY1 Sets VX to VX or VY. (Bitwise OR operation)
-                        self.v[x] = (self.v[x] | self.v[y]);
-                        self.pc += 2;
-                    }
-                    0x0002 => {
-                        // 0x8XY2 Sets VX to VX and VY. (Bitwise AND operation)
-                        self.v[x] = (self.v[x] & self.v[y]);
-                        self.pc += 2;
-                    }
-                    0x0003 => {
-                        // 0x8XY3 Sets VX to VX xor VY
-                        self.v[x] = (self.v[x] ^ self.v[y]);
-                        self.pc += 2;
-                    }
-                    0x0004 => {
-                        // 0x8XY4 Adds VY to VX. VF is set to 1 when there's a
-                        // carry, and to 0 when there isn't
-                        if self.v[y] > (0xFF - self.v[x]) {
-                            self.v[0xF] = 1;
-                        } else {
-                            self.v[0xF] = 0;
-                        }
-                        self.v[x] = self.v[x].wrapping_add(self.v[y]);
-                        self.pc += 2;
-                    }
-                    0x0005 => {
-                        // 0x8XY5 VY is subtracted from VX. VF is set to 0 when
-                        // there's a borrow, and 1 when there isn't
-                        if self.v[x] > self.v[y] {
-                            self.v[0xF] = 1;
-                        } else {
-                            self.v[0xF] = 0;
-                        }
-                        self.v[x] = self.v[x].wrapping_sub(self.v[y]);
-                        self.pc += 2;
-                    }
-                    0x0006 => {
-                        // 0x8XY6 Stores the least significant bit of VX in VF
-                        // and then shifts VX to the right by 1
-                        if self.opcode & 0x1 >= 1 {
-                            self.v[0xF] = 1;
-                        } else {
-                            self.v[0xF] = 0;
-                        }
-                        self.v[x] = self.v[x].wrapping_shr(1);
-                        self.pc += 2;
-                    }
-                    0x0007 => {
-                        // 0x8XY7 Sets VX to VY minus VX. VF is set to 0 when
-                        // there's a borrow, and 1 when there isn't
-                        if self.v[y] > self.v[x] {
-                            self.v[0xF] = 1;
-                        } else {
-                            self.v[0xF] = 0;
-                        }
-                        self.v[x] = self.v[y].wrapping_sub(self.v[x]);
-                        self.pc += 2;
-                    }
-                    0x000E => {
-                        // 0x8XYE Stores the most significant bit of VX in VF
-                        // and then shifts VX to the left by 1
-                        if self.opcode & 0x80 == 0x80 {
-                            self.v[0xF] = 1;
-                        } else {
-                            self.v[0xF] = 0;
-                        }
-                        self.v[x] = self.v[x].wrapping_shl(1);
-                        self.pc += 2;
-                    }
-                    _ => println!("Unknown opcode: {:x}", self.opcode),
+                self.v[x as usize] = self.v[x as usize].wrapping_add(nn);
+                self.pc = next;
+            }
+            LoadReg { x, y } => {
+                // 8XY0 Sets VX to the value of VY
+                self.v[x as usize] = self.v[y as usize];
+                self.pc = next;
+            }
+            Or { x, y } => {
+                // 8XY1 Sets VX to VX or VY. (Bitwise OR operation)
+                self.v[x as usize] |= self.v[y as usize];
+                if self.quirks.vf_reset {
+                    self.v[0xF] = 0;
+                }
+                self.pc = next;
+            }
+            And { x, y } => {
+                // 8XY2 Sets VX to VX and VY. (Bitwise AND operation)
+                self.v[x as usize] &= self.v[y as usize];
+                if self.quirks.vf_reset {
+                    self.v[0xF] = 0;
+                }
+                self.pc = next;
+            }
+            Xor { x, y } => {
+                // 8XY3 Sets VX to VX xor VY
+                self.v[x as usize] ^= self.v[y as usize];
+                if self.quirks.vf_reset {
+                    self.v[0xF] = 0;
                 }
+                self.pc = next;
+            }
+            AddReg { x, y } => {
+                // 8XY4 Adds VY to VX. VF is set to 1 when there's a carry,
+                // and to 0 when there isn't. VF is set last, so the flag
+                // wins when VX is VF
+                let (sum, carry) = self.v[x as usize].overflowing_add(self.v[y as usize]);
+                self.v[x as usize] = sum;
+                self.v[0xF] = carry as u8;
+                self.pc = next;
+            }
+            Sub { x, y } => {
+                // 8XY5 VY is subtracted from VX. VF is set to 0 when there's
+                // a borrow, and 1 when there isn't
+                let (diff, borrow) = self.v[x as usize].overflowing_sub(self.v[y as usize]);
+                self.v[x as usize] = diff;
+                self.v[0xF] = !borrow as u8;
+                self.pc = next;
+            }
+            Shr { x, y } => {
+                // 8XY6 Stores the least significant bit of VX in VF and then
+                // shifts VX to the right by 1 (VY instead of VX with the
+                // shift quirk)
+                let src = if self.quirks.shift_uses_vy {
+                    self.v[y as usize]
+                } else {
+                    self.v[x as usize]
+                };
+                self.v[x as usize] = src >> 1;
+                self.v[0xF] = src & 0x1;
+                self.pc = next;
+            }
+            SubN { x, y } => {
+                // 8XY7 Sets VX to VY minus VX. VF is set to 0 when there's a
+                // borrow, and 1 when there isn't
+                let (diff, borrow) = self.v[y as usize].overflowing_sub(self.v[x as usize]);
+                self.v[x as usize] = diff;
+                self.v[0xF] = !borrow as u8;
+                self.pc = next;
             }
-            0x9000 => {
+            Shl { x, y } => {
+                // 8XYE Stores the most significant bit of VX in VF and then
+                // shifts VX to the left by 1 (VY instead of VX with the
+                // shift quirk)
+                let src = if self.quirks.shift_uses_vy {
+                    self.v[y as usize]
+                } else {
+                    self.v[x as usize]
+                };
+                self.v[x as usize] = src << 1;
+                self.v[0xF] = src >> 7;
+                self.pc = next;
+            }
+            SkipNeReg { x, y } => {
                 // 9XY0 Skips the next instruction if VX doesn't equal VY.
                 // (Usually the next instruction is a jump to skip a code
                 // block)
-                if self.v[x] != self.v[y] {
-                    self.pc += 2;
+                if self.v[x as usize] != self.v[y as usize] {
+                    self.pc = self.next_pc(2 + self.skip_len())?;
+                } else {
+                    self.pc = next;
                 }
-                self.pc += 2;
             }
-            0xA000 => {
+            LoadI { nnn } => {
                 // ANNN set index to NNN position
                 self.index = nnn;
-                self.pc += 2;
+                self.pc = next;
             }
-            0xB000 => {
-                // BNNN Jumps to the address NNN plus V0
-                self.pc = nnn + self.v[0] as u16;
-                // self.pc += 2;
+            JumpV0 { nnn } => {
+                // BNNN Jumps to the address NNN plus V0 (XNN plus VX with
+                // the jump quirk)
+                if self.quirks.jump_uses_vx {
+                    self.pc = nnn + self.v[(nnn >> 8) as usize] as u16;
+                } else {
+                    self.pc = nnn + self.v[0] as u16;
+                }
             }
-            0xC000 => {
+            Random { x, nn } => {
                 // CXNN Sets VX to the result of a bitwise and operation on a
-                // random number (Typically: 0 to 255) and NN
-                let mut rng = rand::thread_rng();
-                let r: u8 = rng.gen_range(0, 255);
-                self.v[x] = r & nn;
-                self.pc += 2;
+                // random number (0 to 255) and NN
+                self.v[x as usize] = self.rng.next_u8() & nn;
+                self.pc = next;
             }
-            0xD000 => {
+            Draw { x, y, n } => {
                 // DXYN Draws a sprite at coordinate (VX, VY) that has a width
                 // of 8 pixels and a height of N+1 pixels. Each row of 8 pixels
                 // is read as bit-coded starting from memory location I; I
                 // value doesn’t change after the execution of this
                 // instruction. As described above, VF is set to 1 if any
                 // screen pixels are flipped from set to unset when the sprite
-                // is drawn, and to 0 if that doesn’t happen
-                let heigh = self.opcode & 0x000F;
-                let mut pixel: u8;
-                self.v[0xF] = 0;
-                for yline in 0..heigh {
-                    pixel = self.memory[(self.index + yline) as usize];
-                    for xline in 0..8 {
-                        if (pixel & (0x80 >> xline)) != 0 {
-                            let pos = (self.v[x] as u16 + xline) as usize
-                                + (self.v[y] as u16 + yline) as usize * w;
-                            if pos >= 2048 {
-                                break;
-                            }
-                            if self.gfx[pos] == 1 {
-                                self.v[0xF] = 1;
-                            }
-                            self.gfx[pos] ^= 1;
-                        }
-                    }
+                // is drawn, and to 0 if that doesn’t happen. The starting
+                // coordinate always wraps around the screen, the parts of
+                // the sprite going off the edges are clipped or wrapped
+                // depending on the clip quirk. On SUPER-CHIP, DXY0 draws a
+                // 16x16 sprite
+                let (vx, vy) = (self.v[x as usize], self.v[y as usize]);
+                if n == 0 && self.platform != Platform::Chip8 {
+                    self.draw_sprite(vx, vy, 16, true)?;
+                } else {
+                    self.draw_sprite(vx, vy, n as usize, false)?;
                 }
-
-                self.draw_flag = true;
-                self.pc += 2;
-            }
-            0xE000 => {
-                match self.opcode & 0x00FF {
-                    0x009E => {
-                        // EX9E Skips the next instruction if the key stored in
-                        // VX is pressed. (Usually the next instruction is a
-                        // jump to skip a code block)
-                        if self.key[self.v[x] as usize] != 0 {
-                            self.pc += 2;
-                        }
-                        self.pc += 2;
-                    }
-                    0x00A1 => {
-                        // EXA1 Skips the next instruction if the key stored in
-                        // VX isn't pressed. (Usually the next instruction is a
-                        // jump to skip a code block)
-                        if self.key[self.v[x] as usize] != 1 {
-                            self.pc += 2;
-                        }
-                        self.pc += 2;
-                    }
-                    _ => println!("Unknown opcode: {:x}", self.opcode),
+                self.pc = next;
+            }
+            SkipKey { x } => {
+                // EX9E Skips the next instruction if the key stored in VX is
+                // pressed. (Usually the next instruction is a jump to skip a
+                // code block)
+                if self.key[(self.v[x as usize] & 0xF) as usize] != 0 {
+                    self.pc = self.next_pc(2 + self.skip_len())?;
+                } else {
+                    self.pc = next;
                 }
             }
-            0xF000 => {
-                match self.opcode & 0x00FF {
-                    0x0007 => {
-                        // FX07 Sets VX to the value of the delay timer
-                        self.v[x] = self.delay_timer;
-                        self.pc += 2;
-                    }
-                    0x000A => {
-                        // FX0A A key press is awaited, and then stored in VX.
-                        // (Blocking Operation. All instruction halted until
-                        // next key event)
-                        let mut pressed: bool = false;
-                        for i in 0..16 {
-                            if self.key[i] == 1 {
-                                self.v[x] = i as u8;
-                                pressed = true;
-                            }
-                        }
-                        if pressed {
-                            self.pc += 2;
-                        }
-                    }
-                    0x0015 => {
-                        // FX15 Sets the delay timer to VX
-                        self.delay_timer = self.v[x];
-                        self.pc += 2;
-                    }
-                    0x0018 => {
-                        // FX18 Sets the sound timer to VX
-                        self.sound_timer = self.v[x];
-                        self.pc += 2;
-                    }
-                    0x001E => {
-                        // FX1E Adds VX to I. VF is not affected
-                        self.index += self.v[x] as u16;
-                        self.pc += 2;
-                    }
-                    0x0029 => {
-                        // FX29 Sets I to the location of the sprite for the
-                        // character in VX. Characters 0-F (in hexadecimal) are
-                        // represented by a 4x5 font
-                        self.index = self.v[x] as u16 * 5;
-                        self.pc += 2;
-                    }
-                    0x0033 => {
-                        self.memory[self.index as usize] = self.v[x] / 100;
-                        self.memory[self.index as usize + 1] = (self.v[x] / 10) % 10;
-                        self.memory[self.index as usize + 2] = (self.v[x] / 100) % 10;
-                        self.pc += 2;
-                    }
-                    0x0055 => {
-                        // FX55 Stores V0 to VX (including VX) in memory
-                        // starting at address I. The offset from I is
-                        // increased by 1 for each value written, but I itself
-                        // is left unmodified
-                        for i in 0..(x + 1) {
-                            self.memory[self.index as usize + i] = self.v[i];
-                        }
-                        self.pc += 2;
-                    }
-                    0x0065 => {
-                        // 0xFX65 Fills V0 to VX (including VX) with values
-                        // from memory starting at address I. The offset from I
-                        // is increased by 1 for each value written, but I
-                        // itself is left unmodified
-                        for i in 0..(x + 1) {
-                            self.v[i] = self.memory[self.index as usize + i];
-                        }
-                        self.pc += 2;
+            SkipNotKey { x } => {
+                // EXA1 Skips the next instruction if the key stored in VX
+                // isn't pressed. (Usually the next instruction is a jump to
+                // skip a code block)
+                if self.key[(self.v[x as usize] & 0xF) as usize] != 1 {
+                    self.pc = self.next_pc(2 + self.skip_len())?;
+                } else {
+                    self.pc = next;
+                }
+            }
+            LoadILong { nnnn } => {
+                // F000 NNNN Sets I to the 16 bit address NNNN stored in the
+                // next two bytes
+                self.index = nnnn;
+                self.pc = next;
+            }
+            Plane { n } => {
+                // FN01 Selects the planes drawn, cleared and scrolled, N
+                // being a bit mask of the 2 planes
+                self.planes = n & 0x3;
+                self.pc = next;
+            }
+            Audio => {
+                // F002 Loads the 16 bytes audio pattern from I
+                self.check_mem(self.index as usize, 16)?;
+                let i = self.index as usize;
+                self.audio_pattern.copy_from_slice(&self.memory[i..i + 16]);
+                self.pc = next;
+            }
+            LoadDelay { x } => {
+                // FX07 Sets VX to the value of the delay timer
+                self.v[x as usize] = self.delay_timer;
+                self.pc = next;
+            }
+            WaitKey { x } => {
+                // FX0A A key press is awaited, and then stored in VX.
+                // (Blocking Operation. All instruction halted until next key
+                // event)
+                let mut pressed: bool = false;
+                for i in 0..16 {
+                    if self.key[i] == 1 {
+                        self.v[x as usize] = i as u8;
+                        pressed = true;
                     }
-                    _ => println!("Unknown opcode: {:x}", self.opcode),
+                }
+                if pressed {
+                    self.pc = next;
+                } else {
+                    outcome = StepOutcome::WaitingForKey;
                 }
             }
-            _ => println!("Unknown opcode: {:x}", self.opcode),
-        }
-        if self.delay_timer > 0 {
-            self.delay_timer -= 1;
-        }
-        if self.sound_timer > 0 {
-            if self.sound_timer == 1 {
-                println!("Beep");
+            SetDelay { x } => {
+                // FX15 Sets the delay timer to VX
+                self.delay_timer = self.v[x as usize];
+                self.pc = next;
+            }
+            SetSound { x } => {
+                // FX18 Sets the sound timer to VX
+                self.sound_timer = self.v[x as usize];
+                self.pc = next;
+            }
+            AddI { x } => {
+                // FX1E Adds VX to I. VF is not affected
+                self.index = self.index.wrapping_add(self.v[x as usize] as u16);
+                self.pc = next;
+            }
+            Font { x } => {
+                // FX29 Sets I to the location of the sprite for the character
+                // in VX. Characters 0-F (in hexadecimal) are represented by a
+                // 4x5 font
+                self.index = self.v[x as usize] as u16 * 5;
+                self.pc = next;
+            }
+            BigFont { x } => {
+                // FX30 Sets I to the location of the 8x10 sprite for the
+                // digit in VX
+                self.index = BIG_FONT_START as u16 + (self.v[x as usize] & 0xF) as u16 * 10;
+                self.pc = next;
+            }
+            Bcd { x } => {
+                // FX33 Stores the binary-coded decimal representation of VX
+                // at I, I+1 and I+2
+                let value = self.v[x as usize];
+                self.check_mem(self.index as usize, 3)?;
+                self.memory[self.index as usize] = value / 100;
+                self.memory[self.index as usize + 1] = (value / 10) % 10;
+                self.memory[self.index as usize + 2] = value % 10;
+                self.cache.invalidate(self.index as usize, 3);
+                self.pc = next;
+            }
+            Pitch { x } => {
+                // FX3A Sets the audio pattern playback pitch to VX
+                self.pitch = self.v[x as usize];
+                self.pc = next;
+            }
+            Store { x } => {
+                // FX55 Stores V0 to VX (including VX) in memory starting at
+                // address I. The offset from I is increased by 1 for each
+                // value written, but I itself is left unmodified unless the
+                // load/store quirk is set
+                let n = x as usize + 1;
+                self.check_mem(self.index as usize, n)?;
+                for i in 0..n {
+                    self.memory[self.index as usize + i] = self.v[i];
+                }
+                self.cache.invalidate(self.index as usize, n);
+                if self.quirks.load_store_increments_i {
+                    self.index = self.index.wrapping_add(n as u16);
+                }
+                self.pc = next;
+            }
+            Load { x } => {
+                // FX65 Fills V0 to VX (including VX) with values from memory
+                // starting at address I. The offset from I is increased by 1
+                // for each value written, but I itself is left unmodified
+                // unless the load/store quirk is set
+                let n = x as usize + 1;
+                self.check_mem(self.index as usize, n)?;
+                for i in 0..n {
+                    self.v[i] = self.memory[self.index as usize + i];
+                }
+                if self.quirks.load_store_increments_i {
+                    self.index = self.index.wrapping_add(n as u16);
+                }
+                self.pc = next;
+            }
+            SaveFlags { x } => {
+                // FX75 Stores V0 to VX in the RPL user flags
+                let n = x as usize + 1;
+                self.rpl[..n].copy_from_slice(&self.v[..n]);
+                self.pc = next;
+            }
+            LoadFlags { x } => {
+                // FX85 Fills V0 to VX from the RPL user flags
+                let n = x as usize + 1;
+                self.v[..n].copy_from_slice(&self.rpl[..n]);
+                self.pc = next;
             }
-            self.sound_timer -= 1;
         }
+        self.opcode = opcode;
+        Ok(outcome)
     }
 }
 
@@ -400,7 +1016,554 @@ mod tests {
     #[test]
     fn test_load_game() {
         let mut c = Chip8::new();
-        c.load_game("Cargo.toml");
-        c.emulate_cycle();
+        c.load_game("Cargo.toml").unwrap();
+        let _ = c.step();
+    }
+    #[test]
+    fn test_load_rom_errors() {
+        let mut c = Chip8::new();
+        match c.load_rom(&[]) {
+            Err(LoadError::EmptyRom) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+        match c.load_rom(&[0; 3585]) {
+            Err(LoadError::RomTooLarge { size, max }) => {
+                assert_eq!(size, 3585);
+                assert_eq!(max, 3584);
+            }
+            r => panic!("unexpected result: {:?}", r),
+        }
+        match c.load_game("non-existing-rom.ch8") {
+            Err(LoadError::Io(_)) => {}
+            r => panic!("unexpected result: {:?}", r),
+        }
+        c.load_rom(&[0xAB; 3584]).unwrap();
+        assert_eq!(c.memory[0xFFF], 0xAB);
+    }
+    #[test]
+    fn test_step_errors() {
+        let mut c = Chip8::new();
+        c.load_rom(&[0x00, 0x01]).unwrap();
+        assert_eq!(
+            c.step(),
+            Err(CpuError::UnknownOpcode {
+                pc: 0x200,
+                opcode: 0x0001
+            })
+        );
+        assert_eq!(c.pc, 0x200);
+
+        // 00EE with an empty stack
+        let mut c = Chip8::new();
+        c.load_rom(&[0x00, 0xEE]).unwrap();
+        assert_eq!(c.step(), Err(CpuError::StackUnderflow { pc: 0x200 }));
+
+        // 2200 calls itself until the stack is full
+        let mut c = Chip8::new();
+        c.load_rom(&[0x22, 0x00]).unwrap();
+        for _ in 0..16 {
+            assert_eq!(c.step(), Ok(StepOutcome::Executed));
+        }
+        assert_eq!(c.step(), Err(CpuError::StackOverflow { pc: 0x200 }));
+
+        // AFFF, FF55 writes past the end of memory
+        let mut c = Chip8::new();
+        c.load_rom(&[0xAF, 0xFF, 0xFF, 0x55]).unwrap();
+        c.step().unwrap();
+        assert_eq!(c.step(), Err(CpuError::MemoryOutOfBounds { addr: 0x1000 }));
+        assert_eq!(c.pc, 0x202);
+
+        // F00A waits for a key
+        let mut c = Chip8::new();
+        c.load_rom(&[0xF0, 0x0A]).unwrap();
+        assert_eq!(c.step(), Ok(StepOutcome::WaitingForKey));
+        c.key[5] = 1;
+        assert_eq!(c.step(), Ok(StepOutcome::Executed));
+        assert_eq!(c.v[0], 5);
+        assert_eq!(c.pc, 0x202);
+    }
+    #[test]
+    fn test_timers() {
+        // 603C F015 F018 1206
+        let mut c = Chip8::new();
+        c.load_rom(&[0x60, 0x3C, 0xF0, 0x15, 0xF0, 0x18, 0x12, 0x06])
+            .unwrap();
+        for _ in 0..10 {
+            c.step().unwrap();
+        }
+        assert_eq!((c.delay_timer, c.sound_timer), (60, 60));
+        assert!(c.is_sound_active());
+        c.run_frame(11).unwrap();
+        assert_eq!((c.delay_timer, c.sound_timer), (59, 59));
+        for _ in 0..59 {
+            c.run_frame(11).unwrap();
+        }
+        assert_eq!((c.delay_timer, c.sound_timer), (0, 0));
+        assert!(!c.is_sound_active());
+        c.tick_timers();
+        assert_eq!((c.delay_timer, c.sound_timer), (0, 0));
+    }
+    #[test]
+    fn test_superchip() {
+        // 00FF: hi-res only exists on SUPER-CHIP
+        let mut c = Chip8::new();
+        c.load_rom(&[0x00, 0xFF]).unwrap();
+        assert!(c.step().is_err());
+
+        // 00FF 6005 F030 6001 D010 00C2 00FC 6207 F275 6200 F285 00FE 00FD
+        let rom = [
+            0x00, 0xFF, 0x60, 0x05, 0xF0, 0x30, 0x60, 0x01, 0xD0, 0x10, 0x00, 0xC2, 0x00, 0xFC,
+            0x62, 0x07, 0xF2, 0x75, 0x62, 0x00, 0xF2, 0x85, 0x00, 0xFE, 0x00, 0xFD,
+        ];
+        let mut c = Chip8::with_platform(Platform::SuperChip, Platform::SuperChip.quirks());
+        c.load_rom(&rom).unwrap();
+        assert_eq!((c.width(), c.height()), (64, 32));
+        c.step().unwrap();
+        assert_eq!((c.width(), c.height()), (128, 64));
+        assert_eq!(c.gfx.len(), 128 * 64);
+        c.step().unwrap();
+        c.step().unwrap();
+        assert_eq!(c.index, 0x50 + 5 * 10);
+        c.step().unwrap();
+        // 16x16 sprite at (V0, V1) = (1, 0), reading 32 bytes from the big
+        // font
+        c.step().unwrap();
+        let row = |c: &Chip8, y: usize| -> Vec<u8> { c.gfx[y * 128..y * 128 + 18].to_vec() };
+        // the big 5 starts with 0xFF 0xFF
+        assert_eq!(row(&c, 0), [vec![0], vec![1; 16], vec![0]].concat());
+        assert_eq!(c.v[0xF], 0);
+        // scroll down 2 and left 4
+        c.step().unwrap();
+        c.step().unwrap();
+        assert_eq!(row(&c, 0), vec![0; 18]);
+        assert_eq!(row(&c, 2), [vec![1; 13], vec![0; 5]].concat());
+        // RPL flags
+        for _ in 0..3 {
+            c.step().unwrap();
+        }
+        assert_eq!(c.v[2], 0);
+        c.step().unwrap();
+        assert_eq!(c.v[2], 7);
+        c.step().unwrap();
+        assert_eq!((c.width(), c.height()), (64, 32));
+        assert_eq!(c.step(), Ok(StepOutcome::Exit));
+        assert_eq!(c.pc, 0x218);
+    }
+    #[test]
+    fn test_xochip() {
+        // F000 NNNN is unknown outside of XO-CHIP
+        let mut c = Chip8::with_platform(Platform::SuperChip, Quirks::schip());
+        c.load_rom(&[0xF0, 0x00, 0x12, 0x34]).unwrap();
+        assert!(c.step().is_err());
+
+        let mut c = Chip8::with_platform(Platform::XoChip, Quirks::xochip());
+        // roms can fill the 64 KiB of memory
+        assert!(c.load_rom(&vec![0; 0x10000 - 0x200]).is_ok());
+
+        // 6001 3001 F000 8000 6102 F000 8000 5212 A400 F000 8000 5213 F201
+        // D015
+        let rom = [
+            0x60, 0x01, 0x30, 0x01, 0xF0, 0x00, 0x80, 0x00, 0x61, 0x02, 0xF0, 0x00, 0x80, 0x00,
+            0x52, 0x12, 0xA4, 0x00, 0xF0, 0x00, 0x80, 0x00, 0x52, 0x13, 0xF2, 0x01, 0xD0, 0x15,
+        ];
+        let mut c = Chip8::with_platform(Platform::XoChip, Quirks::xochip());
+        c.load_rom(&rom).unwrap();
+        c.step().unwrap();
+        // skips the 4 bytes of F000 8000
+        c.step().unwrap();
+        assert_eq!(c.pc, 0x208);
+        c.step().unwrap();
+        c.step().unwrap();
+        assert_eq!(c.index, 0x8000);
+        // 5212 stores V2, V1 at 0x8000
+        c.step().unwrap();
+        assert_eq!(c.memory[0x8000..0x8002], [0, 2]);
+        c.step().unwrap();
+        c.step().unwrap();
+        // 5213 loads 0, 2 back into V2, V1
+        c.v[1] = 9;
+        c.v[2] = 9;
+        c.step().unwrap();
+        assert_eq!((c.v[2], c.v[1]), (0, 2));
+        // draws the 0 digit of the font at (1, 2) on plane 2
+        c.step().unwrap();
+        c.index = 0;
+        c.step().unwrap();
+        assert_eq!(c.gfx[2 * 64 + 1..2 * 64 + 5], [2, 2, 2, 2]);
+        assert!(c.gfx.iter().all(|p| *p == 0 || *p == 2));
+    }
+    #[test]
+    fn test_quirks() {
+        // 6081 6103 8016 801E
+        let rom = [0x60, 0x81, 0x61, 0x03, 0x80, 0x16, 0x80, 0x1E];
+        let mut c = Chip8::with_quirks(Quirks::modern());
+        c.load_rom(&rom).unwrap();
+        c.step().unwrap();
+        c.step().unwrap();
+        c.step().unwrap();
+        assert_eq!((c.v[0], c.v[0xF]), (0x40, 1));
+        c.step().unwrap();
+        assert_eq!((c.v[0], c.v[0xF]), (0x80, 0));
+        let mut c = Chip8::with_quirks(Quirks::vip());
+        c.load_rom(&rom).unwrap();
+        c.step().unwrap();
+        c.step().unwrap();
+        c.step().unwrap();
+        assert_eq!((c.v[0], c.v[0xF]), (0x01, 1));
+        c.step().unwrap();
+        assert_eq!((c.v[0], c.v[0xF]), (0x06, 0));
+
+        // 6F01 6002 8011: VF reset
+        let rom = [0x6F, 0x01, 0x60, 0x02, 0x80, 0x11];
+        for (quirks, vf) in &[(Quirks::vip(), 0), (Quirks::modern(), 1)] {
+            let mut c = Chip8::with_quirks(*quirks);
+            c.load_rom(&rom).unwrap();
+            for _ in 0..3 {
+                c.step().unwrap();
+            }
+            assert_eq!(c.v[0xF], *vf);
+        }
+
+        // 6002 6304 B300: jump
+        let rom = [0x60, 0x02, 0x63, 0x04, 0xB3, 0x00];
+        for (quirks, pc) in &[(Quirks::modern(), 0x302), (Quirks::schip(), 0x304)] {
+            let mut c = Chip8::with_quirks(*quirks);
+            c.load_rom(&rom).unwrap();
+            for _ in 0..3 {
+                c.step().unwrap();
+            }
+            assert_eq!(c.pc, *pc);
+        }
+
+        // A300 F255: load/store
+        let rom = [0xA3, 0x00, 0xF2, 0x55];
+        for (quirks, index) in &[(Quirks::modern(), 0x300), (Quirks::vip(), 0x303)] {
+            let mut c = Chip8::with_quirks(*quirks);
+            c.load_rom(&rom).unwrap();
+            c.step().unwrap();
+            c.step().unwrap();
+            assert_eq!(c.index, *index);
+        }
+
+        // 603E 611E A000 D015: draw the 0 digit at (62, 30)
+        let rom = [0x60, 0x3E, 0x61, 0x1E, 0xA0, 0x00, 0xD0, 0x15];
+        let mut c = Chip8::with_quirks(Quirks::vip());
+        c.load_rom(&rom).unwrap();
+        for _ in 0..4 {
+            c.step().unwrap();
+        }
+        assert_eq!(c.gfx.iter().filter(|p| **p == 1).count(), 3);
+        assert_eq!(c.gfx[1], 0);
+        let mut c = Chip8::with_quirks(Quirks::modern());
+        c.load_rom(&rom).unwrap();
+        for _ in 0..4 {
+            c.step().unwrap();
+        }
+        assert_eq!(c.gfx.iter().filter(|p| **p == 1).count(), 14);
+        assert_eq!(c.gfx[1], 1);
+    }
+    #[test]
+    fn test_disasm() {
+        use disasm::{decode, disassemble, Instruction};
+        assert_eq!(decode(0x8126), Instruction::Shr { x: 1, y: 2 });
+        assert_eq!(decode(0xD01F).to_string(), "DRW V0, V1, 15");
+        assert_eq!(decode(0x00FF).to_string(), "HIGH");
+        assert_eq!(decode(0x5121).to_string(), "DW 0x5121");
+
+        // 2206 A20A 1204 00E0 00EE, and two bytes of data at 0x20A
+        let rom = [
+            0x22, 0x06, 0xA2, 0x0A, 0x12, 0x04, 0x00, 0xE0, 0x00, 0xEE, 0xF0, 0x90,
+        ];
+        let listing = disassemble(&rom, 0x200, false).listing();
+        assert_eq!(
+            listing,
+            "0x200  2206              CALL sub_206\n\
+             0x202  A20A              LD I, data_20A\n\
+             label_204:\n\
+             0x204  1204              JP label_204\n\
+             sub_206:\n\
+             0x206  00E0              CLS\n\
+             0x208  00EE              RET\n\
+             data_20A:\n\
+             0x20A  F090              DB 0xF0, 0x90\n"
+        );
+        let linear = disassemble(&rom, 0x200, true);
+        assert_eq!(linear.items.len(), 6);
+    }
+
+    #[test]
+    fn test_instruction() {
+        // every opcode encodes back to itself
+        for opcode in 0..=0xFFFF {
+            assert_eq!(decode(opcode).encode(), opcode, "{:04X}", opcode);
+        }
+        assert_eq!(decode(0x0123), Instruction::Sys { nnn: 0x123 });
+        assert_eq!(decode(0x00E1), Instruction::Sys { nnn: 0x0E1 });
+        assert_eq!(decode(0x9121), Instruction::Unknown { opcode: 0x9121 });
+        let long = Instruction::LoadILong { nnnn: 0x1234 };
+        assert_eq!(long.bytes(), vec![0xF0, 0x00, 0x12, 0x34]);
+        assert_eq!(long.bytes().len(), long.size());
+
+        assert!(!Instruction::Hires.is_supported(Platform::Chip8));
+        assert!(Instruction::Hires.is_supported(Platform::XoChip));
+        assert!(!Instruction::Audio.is_supported(Platform::SuperChip));
+        assert!(!Instruction::Sys { nnn: 0 }.is_supported(Platform::XoChip));
+
+        let mut c = Chip8::new();
+        c.execute(Instruction::LoadByte { x: 3, nn: 0x42 }).unwrap();
+        assert_eq!((c.v[3], c.pc, c.opcode), (0x42, 0x202, 0x6342));
+        for instruction in [
+            Instruction::LoadByte { x: 16, nn: 1 },
+            Instruction::Jump { nnn: 0x1FFF },
+            Instruction::Draw { x: 0, y: 0, n: 16 },
+            Instruction::Unknown { opcode: 0x6001 },
+        ] {
+            assert_eq!(
+                c.execute(instruction),
+                Err(CpuError::InvalidInstruction {
+                    pc: 0x202,
+                    instruction
+                })
+            );
+        }
+        assert_eq!(c.opcode, 0x6342);
+        assert_eq!(
+            c.execute(Instruction::Hires),
+            Err(CpuError::UnknownOpcode {
+                pc: 0x202,
+                opcode: 0x00FF
+            })
+        );
+        assert_eq!(c.opcode(), 0x6342);
+    }
+    #[test]
+    fn test_backend() {
+        // a subroutine that is rewritten by FX55 between two calls
+        let rom = [
+            0x60, 0x73, 0x61, 0x05, 0xA2, 0x0C, 0x22, 0x0C, 0xF1, 0x55, 0x22, 0x0C, 0x72, 0x01,
+            0x00, 0xEE,
+        ];
+        for backend in [Backend::Interpreter, Backend::Cached] {
+            let mut c = Chip8::new().with_backend(backend);
+            c.load_rom(&rom).unwrap();
+            for _ in 0..10 {
+                c.step().unwrap();
+            }
+            assert_eq!((c.v[2], c.v[3]), (1, 5), "{}", backend);
+            assert_eq!(c.backend(), backend);
+        }
+        let run = |platform, rom: &[u8], steps, backend| {
+            let mut c = Chip8::with_platform(platform, Quirks::xochip()).with_backend(backend);
+            c.load_rom(rom).unwrap();
+            for _ in 0..steps {
+                c.step().unwrap();
+            }
+            c
+        };
+        // 6AC8 7101 A20B 120A 0000 120C FA33 120A: the BCD of 200 turns the
+        // jump at 20A into 1202, back to the 7101 counting the passes in V1
+        let bcd = [
+            0x6A, 0xC8, 0x71, 0x01, 0xA2, 0x0B, 0x12, 0x0A, 0x00, 0x00, 0x12, 0x0C, 0xFA, 0x33,
+            0x12, 0x0A,
+        ];
+        // 5XY2 turning the 7301 of a subroutine into 7205
+        let save = [
+            0x60, 0x72, 0x61, 0x05, 0xA2, 0x10, 0x22, 0x10, 0x50, 0x12, 0x22, 0x10, 0x12, 0x0C,
+            0x00, 0x00, 0x73, 0x01, 0x00, 0xEE,
+        ];
+        // 5XY2 on the last byte of the F000 0230 of a subroutine, turning it
+        // into F000 0240
+        let long = [
+            0x60, 0x40, 0x22, 0x0C, 0xA2, 0x0F, 0x50, 0x02, 0x22, 0x0C, 0x12, 0x0A, 0xF0, 0x00,
+            0x02, 0x30, 0x00, 0xEE,
+        ];
+        // 5XY2 turning the third instruction of the block 7201 7201 7301
+        // 00EE into 7401, out of reach of the look-back of single
+        // instructions
+        let block = [
+            0x60, 0x74, 0x61, 0x01, 0xA2, 0x12, 0x22, 0x0E, 0x50, 0x12, 0x22, 0x0E, 0x12, 0x0C,
+            0x72, 0x01, 0x72, 0x01, 0x73, 0x01, 0x00, 0xEE,
+        ];
+        for backend in [Backend::Interpreter, Backend::Cached] {
+            let c = run(Platform::XoChip, &block, 15, backend);
+            assert_eq!((c.v[2], c.v[3], c.v[4]), (4, 1, 1), "{}", backend);
+            let c = run(Platform::Chip8, &bcd, 13, backend);
+            assert_eq!((c.v[1], c.pc), (3, 0x204), "{}", backend);
+            let c = run(Platform::XoChip, &save, 11, backend);
+            assert_eq!((c.v[2], c.v[3]), (5, 1), "{}", backend);
+            let c = run(Platform::XoChip, &long, 3, backend);
+            assert_eq!(c.index, 0x230, "{}", backend);
+            let c = run(Platform::XoChip, &long, 10, backend);
+            assert_eq!((c.index, c.pc), (0x240, 0x20A), "{}", backend);
+        }
+        assert_eq!("cached".parse(), Ok(Backend::Cached));
+        assert!("jit".parse::<Backend>().is_err());
+    }
+
+    #[test]
+    fn test_save_state() {
+        // 6105 A000 D015 7101 1202: draw a 0 and move it right forever
+        let rom = [0x61, 0x05, 0xA0, 0x00, 0xD0, 0x15, 0x71, 0x01, 0x12, 0x02];
+        let mut c = Chip8::with_platform(Platform::SuperChip, Quirks::schip());
+        c.load_rom(&rom).unwrap();
+        c.delay_timer = 9;
+        c.run_frame(7).unwrap();
+        let state = c.save_state();
+        let (pc, v, gfx, dt) = (c.pc(), *c.v(), c.gfx.clone(), c.delay_timer());
+        c.run_frame(13).unwrap();
+        assert_ne!(c.v(), &v);
+        c.load_state(&state).unwrap();
+        assert_eq!(c.pc(), pc);
+        assert_eq!(c.v(), &v);
+        assert_eq!(c.gfx, gfx);
+        assert_eq!(c.delay_timer(), dt);
+        assert_eq!(c.save_state(), state);
+
+        assert_eq!(c.load_state(b"PNG"), Err(StateError::NotAState));
+        assert_eq!(
+            c.load_state(&state[..state.len() - 1]),
+            Err(StateError::Corrupt)
+        );
+        // the planes, at 72 after the header, the registers, the stack and
+        // the timers
+        let mut bad = state.clone();
+        bad[72] = 0xFF;
+        assert_eq!(c.load_state(&bad), Err(StateError::Corrupt));
+        let mut other = Chip8::with_platform(Platform::SuperChip, Quirks::schip());
+        other.load_rom(&rom[..8]).unwrap();
+        assert_eq!(other.load_state(&state), Err(StateError::RomMismatch));
+        // hi-res with a display of the right size, on a platform without it
+        let mut chip8 = Chip8::new();
+        chip8.load_rom(&rom).unwrap();
+        let mut bad = chip8.save_state();
+        bad[71] = 1;
+        bad.resize(bad.len() + HIRES_W * HIRES_H - W * H, 0);
+        assert_eq!(chip8.load_state(&bad), Err(StateError::Corrupt));
+        let mut other = Chip8::new();
+        other.load_rom(&rom).unwrap();
+        assert_eq!(
+            other.load_state(&state),
+            Err(StateError::PlatformMismatch {
+                state: Platform::SuperChip,
+                machine: Platform::Chip8
+            })
+        );
+    }
+    #[test]
+    fn test_rewind() {
+        // 00FF 6105 A000 D015 7101 1204: switch to hi-res, then draw a 0 and
+        // move it right forever
+        let rom = [
+            0x00, 0xFF, 0x61, 0x05, 0xA0, 0x00, 0xD0, 0x15, 0x71, 0x01, 0x12, 0x04,
+        ];
+        let mut c = Chip8::with_platform(Platform::SuperChip, Quirks::schip());
+        c.load_rom(&rom).unwrap();
+        let mut rewind = Rewind::new(5);
+        let mut states = vec![c.save_state()];
+        rewind.push(&c);
+        for _ in 0..8 {
+            c.run_frame(3).unwrap();
+            states.push(c.save_state());
+            rewind.push(&c);
+        }
+        assert_eq!(rewind.len(), 5);
+        for state in states.iter().rev().skip(1).take(5) {
+            assert!(rewind.rewind(&mut c));
+            assert_eq!(&c.save_state(), state);
+        }
+        assert!(!rewind.rewind(&mut c));
+        assert!(rewind.is_empty());
+
+        // going back over the switch to hi-res
+        let mut c = Chip8::with_platform(Platform::SuperChip, Quirks::schip());
+        c.load_rom(&rom).unwrap();
+        let lores = c.save_state();
+        rewind.clear();
+        rewind.push(&c);
+        c.run_frame(5).unwrap();
+        rewind.push(&c);
+        assert!(rewind.rewind(&mut c));
+        assert_eq!(c.save_state(), lores);
+        assert_eq!(c.width(), 64);
+    }
+    #[test]
+    fn test_rng() {
+        // C0FF 1200: random numbers into V0 forever
+        let rom = [0xC0, 0xFF, 0x12, 0x00];
+        let run = |c: &mut Chip8, n: usize| -> Vec<u8> {
+            (0..n)
+                .map(|_| {
+                    c.step().unwrap();
+                    c.step().unwrap();
+                    c.v()[0]
+                })
+                .collect()
+        };
+        let mut a = Chip8::new().with_rng(42);
+        a.load_rom(&rom).unwrap();
+        let mut b = Chip8::new().with_rng(42);
+        b.load_rom(&rom).unwrap();
+        let numbers = run(&mut a, 2000);
+        assert_eq!(numbers, run(&mut b, 2000));
+        assert_eq!(a.seed(), 42);
+        // the whole range comes out, 255 included
+        for n in 0..=255 {
+            assert!(numbers.contains(&n), "{} never drawn", n);
+        }
+
+        let mut c = Chip8::new().with_rng(43);
+        c.load_rom(&rom).unwrap();
+        assert_ne!(run(&mut c, 10), numbers[..10]);
+
+        // the state of the generator goes into save states
+        let state = a.save_state();
+        let next = run(&mut a, 10);
+        a.load_state(&state).unwrap();
+        assert_eq!(run(&mut a, 10), next);
+    }
+    #[test]
+    fn test_movie() {
+        use movie::{key_mask, keys_from_mask, Movie, MovieError};
+        let mut keys = [0; 16];
+        keys[0x1] = 1;
+        keys[0xF] = 1;
+        assert_eq!(key_mask(&keys), 0x8002);
+        assert_eq!(keys_from_mask(0x8002), keys);
+
+        // F00A 8104 C20F 1200: adds the keys pressed to V1, and randoms to V2
+        let rom = [0xF0, 0x0A, 0x81, 0x04, 0xC2, 0x0F, 0x12, 0x00];
+        let mut c = Chip8::with_platform(Platform::SuperChip, Quirks::vip()).with_rng(5);
+        c.load_rom(&rom).unwrap();
+        let mut movie = Movie::new(&c, 4);
+        for frame in 0..40 {
+            let mut keys = [0; 16];
+            keys[frame % 7] = (frame % 3 == 0) as u8;
+            c.key = keys;
+            movie.push(&keys);
+            c.run_frame(4).unwrap();
+        }
+
+        let movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+        let mut replay = movie.machine(&rom).unwrap();
+        assert_eq!(replay.quirks(), Quirks::vip());
+        let mut frame = 0;
+        while let Some(keys) = movie.keys(frame) {
+            replay.key = keys;
+            replay.run_frame(movie.ipf as usize).unwrap();
+            frame += 1;
+        }
+        assert_eq!(frame, 40);
+        assert_eq!(replay.save_state(), c.save_state());
+
+        assert_eq!(
+            movie.machine(&rom[..6]).err(),
+            Some(MovieError::RomMismatch)
+        );
+        assert_eq!(Movie::from_bytes(b"C8ST"), Err(MovieError::NotAMovie));
+        let bytes = movie.to_bytes();
+        assert_eq!(
+            Movie::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(MovieError::Corrupt)
+        );
     }
 }
@@ -0,0 +1,148 @@
+//! Movies: the keys held on each frame of a run, with what is needed to
+//! replay it exactly.
+//!
+//! Format, all numbers big-endian:
+//! "C8MV", version (u8), platform (u8), quirks (u8, see `Quirks::bits`),
+//! rom hash (u64), seed (u64), instructions per frame (u32), number of
+//! frames (u32), then one u16 per frame with bit N set while key N is held.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::{Chip8, Platform, Quirks};
+
+const MAGIC: &[u8; 4] = b"C8MV";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 4 + 1 + 1 + 1 + 8 + 8 + 4 + 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MovieError {
+    /// The data does not start like a movie
+    NotAMovie,
+    /// The movie was written by an incompatible version of the format
+    UnsupportedVersion(u8),
+    /// The movie was recorded with another rom
+    RomMismatch,
+    /// The movie is truncated or holds invalid values
+    Corrupt,
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MovieError::NotAMovie => write!(f, "not a movie"),
+            MovieError::UnsupportedVersion(v) => write!(f, "unsupported movie version {}", v),
+            MovieError::RomMismatch => write!(f, "movie was recorded with another rom"),
+            MovieError::Corrupt => write!(f, "movie is corrupt"),
+        }
+    }
+}
+
+impl Error for MovieError {}
+
+/// Keys held as a mask with bit N set for key N
+pub fn key_mask(keys: &[u8; 16]) -> u16 {
+    keys.iter()
+        .enumerate()
+        .fold(0, |mask, (i, k)| if *k != 0 { mask | 1 << i } else { mask })
+}
+
+/// Inverse of `key_mask`
+pub fn keys_from_mask(mask: u16) -> [u8; 16] {
+    let mut keys = [0; 16];
+    for (i, k) in keys.iter_mut().enumerate() {
+        *k = (mask >> i & 1) as u8;
+    }
+    keys
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub rom_hash: u64,
+    pub seed: u64,
+    pub ipf: u32,
+    /// Keys held on each frame, see `key_mask`
+    pub frames: Vec<u16>,
+}
+
+impl Movie {
+    /// Starts an empty movie of the machine, which should have just loaded
+    /// its rom
+    pub fn new(chip8: &Chip8, ipf: u32) -> Movie {
+        Movie {
+            platform: chip8.platform(),
+            quirks: chip8.quirks(),
+            rom_hash: chip8.rom_hash(),
+            seed: chip8.seed(),
+            ipf,
+            frames: Vec::new(),
+        }
+    }
+    /// Creates the machine the movie was recorded on, and loads the rom
+    /// into it
+    pub fn machine(&self, rom: &[u8]) -> Result<Chip8, MovieError> {
+        let mut c = Chip8::with_platform(self.platform, self.quirks).with_rng(self.seed);
+        if c.load_rom(rom).is_err() || c.rom_hash() != self.rom_hash {
+            return Err(MovieError::RomMismatch);
+        }
+        Ok(c)
+    }
+    /// Appends a frame with the given keys held
+    pub fn push(&mut self, keys: &[u8; 16]) {
+        self.frames.push(key_mask(keys));
+    }
+    /// Keys held on a frame, None past the end of the movie
+    pub fn keys(&self, frame: usize) -> Option<[u8; 16]> {
+        self.frames.get(frame).map(|mask| keys_from_mask(*mask))
+    }
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut b = Vec::with_capacity(HEADER_LEN + self.frames.len() * 2);
+        b.extend_from_slice(MAGIC);
+        b.push(VERSION);
+        b.push(self.platform.code());
+        b.push(self.quirks.bits());
+        b.extend_from_slice(&self.rom_hash.to_be_bytes());
+        b.extend_from_slice(&self.seed.to_be_bytes());
+        b.extend_from_slice(&self.ipf.to_be_bytes());
+        b.extend_from_slice(&(self.frames.len() as u32).to_be_bytes());
+        for mask in &self.frames {
+            b.extend_from_slice(&mask.to_be_bytes());
+        }
+        b
+    }
+    pub fn from_bytes(b: &[u8]) -> Result<Movie, MovieError> {
+        if b.len() < 5 || &b[..4] != MAGIC {
+            return Err(MovieError::NotAMovie);
+        }
+        if b[4] != VERSION {
+            return Err(MovieError::UnsupportedVersion(b[4]));
+        }
+        if b.len() < HEADER_LEN {
+            return Err(MovieError::Corrupt);
+        }
+        let u32_at = |i: usize| u32::from_be_bytes([b[i], b[i + 1], b[i + 2], b[i + 3]]);
+        let u64_at = |i: usize| (u32_at(i) as u64) << 32 | u32_at(i + 4) as u64;
+        let platform = Platform::from_code(b[5]).ok_or(MovieError::Corrupt)?;
+        if u32_at(23) == 0 {
+            return Err(MovieError::Corrupt);
+        }
+        let count = u32_at(27) as usize;
+        let data = &b[HEADER_LEN..];
+        if data.len() != count * 2 {
+            return Err(MovieError::Corrupt);
+        }
+        Ok(Movie {
+            platform,
+            quirks: Quirks::from_bits(b[6]),
+            rom_hash: u64_at(7),
+            seed: u64_at(15),
+            ipf: u32_at(23),
+            frames: data
+                .chunks(2)
+                .map(|f| u16::from_be_bytes([f[0], f[1]]))
+                .collect(),
+        })
+    }
+}
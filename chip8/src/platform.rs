@@ -0,0 +1,90 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::Quirks;
+
+/// Instruction set and display the machine emulates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Platform {
+    /// Original CHIP-8, 64x32 display
+    #[default]
+    Chip8,
+    /// SUPER-CHIP 1.1, adds a 128x64 hi-res mode, scrolling, 16x16 sprites,
+    /// a big font and the RPL user flags
+    SuperChip,
+    /// XO-CHIP, extends SUPER-CHIP with 64 KiB of memory, a second display
+    /// plane and audio patterns
+    XoChip,
+}
+
+impl Platform {
+    /// Quirks expected by most roms written for the platform
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks::modern(),
+            Platform::SuperChip => Quirks::schip(),
+            Platform::XoChip => Quirks::xochip(),
+        }
+    }
+    /// Number identifying the platform in save states and movies
+    pub(crate) fn code(self) -> u8 {
+        match self {
+            Platform::Chip8 => 0,
+            Platform::SuperChip => 1,
+            Platform::XoChip => 2,
+        }
+    }
+    pub(crate) fn from_code(code: u8) -> Option<Platform> {
+        match code {
+            0 => Some(Platform::Chip8),
+            1 => Some(Platform::SuperChip),
+            2 => Some(Platform::XoChip),
+            _ => None,
+        }
+    }
+    /// Bytes of addressable memory
+    pub fn memory_size(self) -> usize {
+        match self {
+            Platform::Chip8 | Platform::SuperChip => 4096,
+            Platform::XoChip => 65536,
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Platform::Chip8 => write!(f, "chip8"),
+            Platform::SuperChip => write!(f, "schip"),
+            Platform::XoChip => write!(f, "xochip"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownPlatform(String);
+
+impl fmt::Display for UnknownPlatform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "unknown platform '{}', expected one of: chip8, schip, xochip",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for UnknownPlatform {}
+
+impl FromStr for Platform {
+    type Err = UnknownPlatform;
+
+    fn from_str(s: &str) -> Result<Platform, UnknownPlatform> {
+        match s.to_lowercase().as_str() {
+            "chip8" | "chip-8" => Ok(Platform::Chip8),
+            "schip" | "superchip" | "super-chip" => Ok(Platform::SuperChip),
+            "xochip" | "xo-chip" => Ok(Platform::XoChip),
+            _ => Err(UnknownPlatform(s.to_string())),
+        }
+    }
+}
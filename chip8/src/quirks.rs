@@ -0,0 +1,127 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Behaviours in which CHIP-8 interpreters disagree. Each ROM was written
+/// against one interpreter, so it has to run with the quirks of that one.
+/// https://github.com/Timendus/chip8-test-suite#quirks-test
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY6/8XYE store VY shifted into VX, instead of shifting VX in place
+    pub shift_uses_vy: bool,
+    /// FX55/FX65 leave I pointing after the last register accessed
+    pub load_store_increments_i: bool,
+    /// BNNN is read as BXNN, jumping to XNN plus VX instead of NNN plus V0
+    pub jump_uses_vx: bool,
+    /// DXYN clips sprites at the screen edges instead of wrapping them around
+    pub clip_sprites: bool,
+    /// 8XY1/8XY2/8XY3 reset VF to 0
+    pub vf_reset: bool,
+}
+
+impl Quirks {
+    /// Original COSMAC VIP interpreter
+    pub fn vip() -> Quirks {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            clip_sprites: true,
+            vf_reset: true,
+        }
+    }
+    /// CHIP-48 on the HP-48 calculators. It incremented I by X instead of
+    /// X + 1 on FX55/FX65, which is approximated here as X + 1.
+    pub fn chip48() -> Quirks {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_increments_i: true,
+            jump_uses_vx: true,
+            clip_sprites: true,
+            vf_reset: false,
+        }
+    }
+    /// SUPER-CHIP 1.1
+    pub fn schip() -> Quirks {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_increments_i: false,
+            jump_uses_vx: true,
+            clip_sprites: true,
+            vf_reset: false,
+        }
+    }
+    /// XO-CHIP as implemented by Octo
+    pub fn xochip() -> Quirks {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            jump_uses_vx: false,
+            clip_sprites: false,
+            vf_reset: false,
+        }
+    }
+    /// Behaviour described in Cowgod's technical reference, followed by most
+    /// modern interpreters
+    pub fn modern() -> Quirks {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_increments_i: false,
+            jump_uses_vx: false,
+            clip_sprites: false,
+            vf_reset: false,
+        }
+    }
+    /// The quirks packed as bits, in the order of the fields
+    pub(crate) fn bits(self) -> u8 {
+        self.shift_uses_vy as u8
+            | (self.load_store_increments_i as u8) << 1
+            | (self.jump_uses_vx as u8) << 2
+            | (self.clip_sprites as u8) << 3
+            | (self.vf_reset as u8) << 4
+    }
+    pub(crate) fn from_bits(bits: u8) -> Quirks {
+        Quirks {
+            shift_uses_vy: bits & 1 != 0,
+            load_store_increments_i: bits & 1 << 1 != 0,
+            jump_uses_vx: bits & 1 << 2 != 0,
+            clip_sprites: bits & 1 << 3 != 0,
+            vf_reset: bits & 1 << 4 != 0,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::modern()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownQuirks(String);
+
+impl fmt::Display for UnknownQuirks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "unknown quirks profile '{}', expected one of: vip, chip48, schip, xochip, modern",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for UnknownQuirks {}
+
+impl FromStr for Quirks {
+    type Err = UnknownQuirks;
+
+    fn from_str(s: &str) -> Result<Quirks, UnknownQuirks> {
+        match s.to_lowercase().as_str() {
+            "vip" | "cosmac" => Ok(Quirks::vip()),
+            "chip48" | "chip-48" => Ok(Quirks::chip48()),
+            "schip" | "superchip" | "super-chip" => Ok(Quirks::schip()),
+            "xochip" | "xo-chip" => Ok(Quirks::xochip()),
+            "modern" => Ok(Quirks::modern()),
+            _ => Err(UnknownQuirks(s.to_string())),
+        }
+    }
+}
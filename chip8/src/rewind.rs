@@ -0,0 +1,128 @@
+use std::collections::VecDeque;
+
+use crate::Chip8;
+
+/// How to get back to the previous state from the one after it
+enum Delta {
+    /// XOR between both states, run-length encoded, see `encode`
+    Xor(Vec<u8>),
+    /// Whole previous state, when the states differ in size because the
+    /// display changed resolution
+    Full(Vec<u8>),
+}
+
+/// Ring buffer of the last states of a machine, to play it back in
+/// reverse. Only the newest state is kept whole; the older ones are stored
+/// as deltas, which are small as a frame changes few bytes of memory and
+/// display.
+pub struct Rewind {
+    capacity: usize,
+    current: Option<Vec<u8>>,
+    deltas: VecDeque<Delta>,
+}
+
+impl Rewind {
+    /// Keeps up to `frames` states besides the newest one
+    pub fn new(frames: usize) -> Rewind {
+        Rewind {
+            capacity: frames,
+            current: None,
+            deltas: VecDeque::with_capacity(frames),
+        }
+    }
+    /// Records the state of the machine, dropping the oldest one when full
+    pub fn push(&mut self, chip8: &Chip8) {
+        let state = chip8.save_state();
+        if let Some(current) = self.current.take() {
+            let delta = if current.len() == state.len() {
+                Delta::Xor(encode(&current, &state))
+            } else {
+                Delta::Full(current)
+            };
+            if self.deltas.len() == self.capacity {
+                self.deltas.pop_front();
+            }
+            if self.capacity > 0 {
+                self.deltas.push_back(delta);
+            }
+        }
+        self.current = Some(state);
+    }
+    /// Moves the machine one state back. Returns false, leaving it
+    /// untouched, when there are no older states.
+    pub fn rewind(&mut self, chip8: &mut Chip8) -> bool {
+        let (current, delta) = match (self.current.as_ref(), self.deltas.pop_back()) {
+            (Some(current), Some(delta)) => (current, delta),
+            _ => return false,
+        };
+        let previous = match delta {
+            Delta::Xor(delta) => decode(current, &delta),
+            Delta::Full(previous) => previous,
+        };
+        if chip8.load_state(&previous).is_err() {
+            self.clear();
+            return false;
+        }
+        self.current = Some(previous);
+        true
+    }
+    /// Number of states the machine can be moved back
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+    pub fn clear(&mut self) {
+        self.current = None;
+        self.deltas.clear();
+    }
+}
+
+/// XORs two states of the same size and encodes the result as runs of a
+/// zero count followed by a count of literal bytes and the literals, with
+/// counts up to 255
+fn encode(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < a.len() {
+        let zeros_start = i;
+        while i < a.len() && i - zeros_start < 255 && a[i] == b[i] {
+            i += 1;
+        }
+        let literals_start = i;
+        while i < a.len() && i - literals_start < 255 && a[i] != b[i] {
+            i += 1;
+        }
+        // trailing zeros are implied
+        if literals_start == i && i == a.len() {
+            break;
+        }
+        out.push((literals_start - zeros_start) as u8);
+        out.push((i - literals_start) as u8);
+        out.extend(
+            a[literals_start..i]
+                .iter()
+                .zip(&b[literals_start..i])
+                .map(|(a, b)| a ^ b),
+        );
+    }
+    out
+}
+
+/// Applies a delta from `encode` to a state
+fn decode(state: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut out = state.to_vec();
+    let (mut i, mut pos) = (0, 0);
+    while i + 1 < delta.len() {
+        pos += delta[i] as usize;
+        let literals = delta[i + 1] as usize;
+        i += 2;
+        for b in &delta[i..i + literals] {
+            out[pos] ^= b;
+            pos += 1;
+        }
+        i += literals;
+    }
+    out
+}
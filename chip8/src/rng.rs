@@ -0,0 +1,47 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// xorshift64* generator for CXNN. It is seedable so runs can be
+/// reproduced, and its state is small enough to go into save states.
+/// https://en.wikipedia.org/wiki/Xorshift#xorshift*
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Any seed is valid, it goes through splitmix64 as xorshift needs a
+    /// non-zero state
+    pub fn new(seed: u64) -> Rng {
+        let mut z = seed.wrapping_add(0x9E3779B97F4A7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^= z >> 31;
+        Rng {
+            state: if z == 0 { 1 } else { z },
+        }
+    }
+    /// Seed taken from the clock, for runs that don't need to be reproduced
+    pub fn random_seed() -> u64 {
+        match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_secs() ^ (d.subsec_nanos() as u64) << 32,
+            Err(_) => 0,
+        }
+    }
+    pub fn next_u8(&mut self) -> u8 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        (self.state.wrapping_mul(0x2545F4914F6CDD1D) >> 56) as u8
+    }
+    pub(crate) fn state(&self) -> u64 {
+        self.state
+    }
+    /// Restores a state from `state()`, None if it is not a valid one
+    pub(crate) fn from_state(state: u64) -> Option<Rng> {
+        if state == 0 {
+            None
+        } else {
+            Some(Rng { state })
+        }
+    }
+}
@@ -0,0 +1,82 @@
+//! Builder methods that set the machine state directly, to test a single
+//! instruction without a rom that gets the machine there first:
+//!
+//! ```
+//! use chip8::Chip8;
+//!
+//! let mut c = Chip8::new().with_v(0, 0xFF).with_v(1, 0x01).with_opcode(0x8014);
+//! c.step().unwrap();
+//! assert_eq!(c.v()[0], 0x00);
+//! assert_eq!(c.v()[0xF], 1);
+//! ```
+
+use crate::Chip8;
+
+impl Chip8 {
+    /// Sets the register VX. Panics if X is above 0xF
+    pub fn with_v(mut self, x: usize, value: u8) -> Chip8 {
+        self.v[x] = value;
+        self
+    }
+    /// Sets the I register
+    pub fn with_index(mut self, index: u16) -> Chip8 {
+        self.index = index;
+        self
+    }
+    /// Sets the address of the next instruction
+    pub fn with_pc(mut self, pc: u16) -> Chip8 {
+        self.pc = pc;
+        self
+    }
+    /// Sets the return addresses of the subroutine calls in progress,
+    /// outermost first. Panics with more than 16
+    pub fn with_stack(mut self, stack: &[u16]) -> Chip8 {
+        assert!(stack.len() <= self.stack.len(), "the stack has 16 levels");
+        self.stack = [0; 16];
+        self.stack[..stack.len()].copy_from_slice(stack);
+        self.sp = stack.len();
+        self
+    }
+    /// Writes bytes to memory starting at `addr`. Panics if they don't fit
+    pub fn with_memory(mut self, addr: usize, bytes: &[u8]) -> Chip8 {
+        self.memory[addr..addr + bytes.len()].copy_from_slice(bytes);
+        self.cache.invalidate(addr, bytes.len());
+        self
+    }
+    /// Writes an instruction at the address of the next one
+    pub fn with_opcode(self, opcode: u16) -> Chip8 {
+        let pc = self.pc as usize;
+        self.with_memory(pc, &opcode.to_be_bytes())
+    }
+    /// Sets the delay and sound timers
+    pub fn with_timers(mut self, delay: u8, sound: u8) -> Chip8 {
+        self.delay_timer = delay;
+        self.sound_timer = sound;
+        self
+    }
+    /// Holds a key down. Panics if the key is above 0xF
+    pub fn with_key(mut self, key: usize) -> Chip8 {
+        self.key[key] = 1;
+        self
+    }
+    /// Switches to the 128x64 display of SUPER-CHIP, or back to 64x32,
+    /// clearing it
+    pub fn with_hires(mut self, hires: bool) -> Chip8 {
+        self.set_hires(hires);
+        self.draw_flag = false;
+        self
+    }
+    /// Lights a pixel of the display on the given planes, 1 for the first
+    /// one. Panics if it is outside of the display
+    pub fn with_pixel(mut self, x: usize, y: usize, planes: u8) -> Chip8 {
+        let w = self.width();
+        assert!(x < w && y < self.height(), "pixel outside of the display");
+        self.gfx[y * w + x] = planes;
+        self
+    }
+    /// Selects the XO-CHIP planes drawn, cleared and scrolled
+    pub fn with_planes(mut self, planes: u8) -> Chip8 {
+        self.planes = planes & 0x3;
+        self
+    }
+}
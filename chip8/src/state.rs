@@ -0,0 +1,184 @@
+//! Save states: the whole machine serialized to bytes.
+//!
+//! Format, all numbers big-endian:
+//! "C8ST", version (u8), platform (u8), rom hash (u64), pc, index and
+//! opcode (u16), V0-VF, sp (u8), stack (16 u16), delay and sound timers,
+//! hires and planes (u8), RPL flags, audio pattern, pitch, keys, random
+//! number generator state (u64), then the memory and the display, whose
+//! sizes follow from the platform and hires.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::rng::Rng;
+use crate::{Chip8, Instruction, Platform, H, HIRES_H, HIRES_W, W};
+
+const MAGIC: &[u8; 4] = b"C8ST";
+const VERSION: u8 = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateError {
+    /// The data does not start like a save state
+    NotAState,
+    /// The state was written by an incompatible version of the format
+    UnsupportedVersion(u8),
+    /// The state was saved on another platform
+    PlatformMismatch { state: Platform, machine: Platform },
+    /// The state was saved while running another rom
+    RomMismatch,
+    /// The state is truncated or holds invalid values
+    Corrupt,
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::NotAState => write!(f, "not a save state"),
+            StateError::UnsupportedVersion(v) => {
+                write!(f, "unsupported save state version {}", v)
+            }
+            StateError::PlatformMismatch { state, machine } => write!(
+                f,
+                "save state is for the {} platform, but the machine is {}",
+                state, machine
+            ),
+            StateError::RomMismatch => write!(f, "save state was made with another rom"),
+            StateError::Corrupt => write!(f, "save state is corrupt"),
+        }
+    }
+}
+
+impl Error for StateError {}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], StateError> {
+        let b = self
+            .data
+            .get(self.pos..self.pos + n)
+            .ok_or(StateError::Corrupt)?;
+        self.pos += n;
+        Ok(b)
+    }
+    fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+    fn u16(&mut self) -> Result<u16, StateError> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+    fn u64(&mut self) -> Result<u64, StateError> {
+        let mut b = [0; 8];
+        b.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_be_bytes(b))
+    }
+    fn array16(&mut self) -> Result<[u8; 16], StateError> {
+        let mut a = [0; 16];
+        a.copy_from_slice(self.bytes(16)?);
+        Ok(a)
+    }
+    fn bool(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Corrupt),
+        }
+    }
+}
+
+impl Chip8 {
+    /// Serializes the whole machine. The quirks are not part of the state,
+    /// they are set when the machine is created.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut s = Vec::with_capacity(128 + self.memory.len() + self.gfx.len());
+        s.extend_from_slice(MAGIC);
+        s.push(VERSION);
+        s.push(self.platform.code());
+        s.extend_from_slice(&self.rom_hash.to_be_bytes());
+        s.extend_from_slice(&self.pc.to_be_bytes());
+        s.extend_from_slice(&self.index.to_be_bytes());
+        s.extend_from_slice(&self.opcode.to_be_bytes());
+        s.extend_from_slice(&self.v);
+        s.push(self.sp as u8);
+        for addr in &self.stack {
+            s.extend_from_slice(&addr.to_be_bytes());
+        }
+        s.push(self.delay_timer);
+        s.push(self.sound_timer);
+        s.push(self.hires as u8);
+        s.push(self.planes);
+        s.extend_from_slice(&self.rpl);
+        s.extend_from_slice(&self.audio_pattern);
+        s.push(self.pitch);
+        s.extend_from_slice(&self.key);
+        s.extend_from_slice(&self.rng.state().to_be_bytes());
+        s.extend_from_slice(&self.memory);
+        s.extend_from_slice(&self.gfx);
+        s
+    }
+    /// Restores a state made by `save_state` on the same platform and rom.
+    /// On error the machine is left untouched.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        let mut r = Reader {
+            data: state,
+            pos: 0,
+        };
+        if r.bytes(4).map_err(|_| StateError::NotAState)? != MAGIC {
+            return Err(StateError::NotAState);
+        }
+        let version = r.u8()?;
+        if version != VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        let platform = Platform::from_code(r.u8()?).ok_or(StateError::Corrupt)?;
+        if platform != self.platform {
+            return Err(StateError::PlatformMismatch {
+                state: platform,
+                machine: self.platform,
+            });
+        }
+        if r.u64()? != self.rom_hash {
+            return Err(StateError::RomMismatch);
+        }
+
+        let mut c = self.clone();
+        c.pc = r.u16()?;
+        c.index = r.u16()?;
+        c.opcode = r.u16()?;
+        c.v = r.array16()?;
+        c.sp = r.u8()? as usize;
+        if c.sp > c.stack.len() {
+            return Err(StateError::Corrupt);
+        }
+        for addr in c.stack.iter_mut() {
+            *addr = r.u16()?;
+        }
+        c.delay_timer = r.u8()?;
+        c.sound_timer = r.u8()?;
+        c.hires = r.bool()?;
+        c.planes = r.u8()?;
+        if c.hires && !Instruction::Hires.is_supported(platform) || c.planes > 3 {
+            return Err(StateError::Corrupt);
+        }
+        c.rpl = r.array16()?;
+        c.audio_pattern = r.array16()?;
+        c.pitch = r.u8()?;
+        c.key = r.array16()?;
+        c.rng = Rng::from_state(r.u64()?).ok_or(StateError::Corrupt)?;
+        let memory_len = c.memory.len();
+        c.memory.copy_from_slice(r.bytes(memory_len)?);
+        c.cache.clear();
+        let gfx_len = if c.hires { HIRES_W * HIRES_H } else { W * H };
+        c.gfx = r.bytes(gfx_len)?.to_vec();
+        if r.pos != state.len() {
+            return Err(StateError::Corrupt);
+        }
+        c.draw_flag = true;
+        *self = c;
+        Ok(())
+    }
+}
@@ -0,0 +1,298 @@
+//! Octo sources assembled with `chip8::asm`, checked against the encoding
+//! of the disassembler and run on the interpreter.
+
+use chip8::asm::{assemble, AsmError, AsmErrorKind};
+use chip8::disasm::{decode_at, disassemble, Item};
+use chip8::{Chip8, Instruction, Platform, Quirks, StepOutcome};
+
+/// Runs a rom until it exits with 00FD, on XO-CHIP to have all the
+/// instructions
+fn run(source: &str) -> Chip8 {
+    let mut c = Chip8::with_platform(Platform::XoChip, Quirks::xochip()).with_rng(0);
+    c.load_rom(&assemble(source).unwrap()).unwrap();
+    for _ in 0..10_000 {
+        if c.step().unwrap() == StepOutcome::Exit {
+            return c;
+        }
+    }
+    panic!("the rom didn't exit");
+}
+
+fn error(source: &str) -> (usize, usize, AsmErrorKind) {
+    let AsmError { line, column, kind } = assemble(source).unwrap_err();
+    (line, column, kind)
+}
+
+#[test]
+fn every_instruction() {
+    use Instruction::*;
+    #[rustfmt::skip]
+    let cases = [
+        ("clear", Cls),
+        ("return", Ret),
+        (";", Ret),
+        ("scroll-down 3", ScrollDown { n: 3 }),
+        ("scroll-up 15", ScrollUp { n: 15 }),
+        ("scroll-right", ScrollRight),
+        ("scroll-left", ScrollLeft),
+        ("exit", Exit),
+        ("lores", Lores),
+        ("hires", Hires),
+        ("jump 0x345", Jump { nnn: 0x345 }),
+        (":call 0x345", Call { nnn: 0x345 }),
+        ("if v1 != 0x42 then", SkipEqByte { x: 1, nn: 0x42 }),
+        ("if v1 == 0x42 then", SkipNeByte { x: 1, nn: 0x42 }),
+        ("if v1 != v2 then", SkipEqReg { x: 1, y: 2 }),
+        ("save v1 - v2", SaveRange { x: 1, y: 2 }),
+        ("load v3 - v1", LoadRange { x: 3, y: 1 }),
+        ("vA := 255", LoadByte { x: 0xA, nn: 0xFF }),
+        ("va += 7", AddByte { x: 0xA, nn: 7 }),
+        ("va -= 1", AddByte { x: 0xA, nn: 0xFF }),
+        ("v1 := v2", LoadReg { x: 1, y: 2 }),
+        ("v1 |= v2", Or { x: 1, y: 2 }),
+        ("v1 &= v2", And { x: 1, y: 2 }),
+        ("v1 ^= v2", Xor { x: 1, y: 2 }),
+        ("v1 += v2", AddReg { x: 1, y: 2 }),
+        ("v1 -= v2", Sub { x: 1, y: 2 }),
+        ("v1 >>= v2", Shr { x: 1, y: 2 }),
+        ("v1 =- v2", SubN { x: 1, y: 2 }),
+        ("v1 <<= v2", Shl { x: 1, y: 2 }),
+        ("if v1 == v2 then", SkipNeReg { x: 1, y: 2 }),
+        ("i := 0xFED", LoadI { nnn: 0xFED }),
+        ("jump0 0x300", JumpV0 { nnn: 0x300 }),
+        ("v3 := random 0x0F", Random { x: 3, nn: 0x0F }),
+        ("sprite v1 v2 15", Draw { x: 1, y: 2, n: 15 }),
+        ("if v4 -key then", SkipKey { x: 4 }),
+        ("if v4 key then", SkipNotKey { x: 4 }),
+        ("i := long 0xBEEF", LoadILong { nnnn: 0xBEEF }),
+        ("plane 3", Plane { n: 3 }),
+        ("audio", Audio),
+        ("v5 := delay", LoadDelay { x: 5 }),
+        ("v5 := key", WaitKey { x: 5 }),
+        ("delay := v5", SetDelay { x: 5 }),
+        ("buzzer := v5", SetSound { x: 5 }),
+        ("i += v5", AddI { x: 5 }),
+        ("i := hex v5", Font { x: 5 }),
+        ("i := bighex v5", BigFont { x: 5 }),
+        ("bcd v5", Bcd { x: 5 }),
+        ("pitch := v5", Pitch { x: 5 }),
+        ("save v5", Store { x: 5 }),
+        ("load v5", Load { x: 5 }),
+        ("saveflags v5", SaveFlags { x: 5 }),
+        ("loadflags v5", LoadFlags { x: 5 }),
+    ];
+    for (source, instruction) in cases {
+        let rom = assemble(source).unwrap();
+        assert_eq!(rom, instruction.bytes(), "{}", source);
+        // and back through the disassembler
+        assert_eq!(
+            decode_at(&rom, 0),
+            Some((instruction, instruction.size())),
+            "{}",
+            source
+        );
+    }
+}
+
+#[test]
+fn labels() {
+    let source = "
+        : ball 0xFF 0b10000001 -1   # raw bytes before main
+        : main
+            i := ball
+            draw
+            jump done
+        : draw
+            sprite v0 v1 3
+            return
+        : done
+            exit
+    ";
+    let rom = assemble(source).unwrap();
+    // a jump to main is put first, and every label moves by 2
+    #[rustfmt::skip]
+    assert_eq!(rom, [
+        0x12, 0x05,
+        0xFF, 0x81, 0xFF,
+        0xA2, 0x02,
+        0x22, 0x0B,
+        0x12, 0x0F,
+        0xD0, 0x13,
+        0x00, 0xEE,
+        0x00, 0xFD,
+    ]);
+    let d = disassemble(&rom, 0x200, false);
+    for item in &d.items {
+        if let Item::Code {
+            bytes, instruction, ..
+        } = item
+        {
+            assert_eq!(bytes, &instruction.bytes());
+        }
+    }
+    assert_eq!(d.labels.get(&0x20B).unwrap(), "sub_20B");
+
+    // without main the program starts at the first byte
+    assert_eq!(assemble(": start jump start").unwrap(), [0x12, 0x00]);
+}
+
+#[test]
+fn consts_and_aliases() {
+    let source = "
+        :const SPEED 3
+        :const LIMIT SPEED
+        :alias x v4
+        : main
+            x := SPEED
+            x += LIMIT
+            :byte LIMIT
+            LIMIT
+    ";
+    assert_eq!(
+        assemble(source).unwrap(),
+        [0x64, 0x03, 0x74, 0x03, 0x03, 0x03]
+    );
+}
+
+#[test]
+fn control_flow() {
+    // sums 1 to 10 in v1, counts the even numbers in v2 and the others in v3
+    let c = run("
+        :alias n v0
+        : main
+            n := 0
+            loop
+                n += 1
+                v1 += n
+                v4 := n
+                v5 := 1
+                v4 &= v5
+                if v4 == 0 begin
+                    v2 += 1
+                else
+                    v3 += 1
+                end
+                while n != 10
+            again
+            if v1 == 55 then v6 := 1
+            if v1 != 55 then v7 := 1
+            exit
+    ");
+    assert_eq!(&c.v()[..8], &[10, 55, 5, 5, 0, 1, 1, 0]);
+
+    // nested loops, the while leaves the inner one
+    let c = run("
+        : main
+            loop
+                v1 := 0
+                loop
+                    while v1 != 3
+                    v1 += 1
+                    v2 += 1
+                again
+                v0 += 1
+                if v0 == 4 then exit
+            again
+    ");
+    assert_eq!(&c.v()[..3], &[4, 3, 12]);
+}
+
+#[test]
+fn errors() {
+    use AsmErrorKind::*;
+    assert_eq!(
+        error("v0 := 5\n  v1 := vg"),
+        (2, 9, InvalidNumber("vg".to_string()))
+    );
+    assert_eq!(
+        error("v0 += 256"),
+        (
+            1,
+            7,
+            OutOfRange {
+                value: 256,
+                min: -128,
+                max: 255
+            }
+        )
+    );
+    assert_eq!(
+        error("sprite v0 v1 16"),
+        (
+            1,
+            14,
+            OutOfRange {
+                value: 16,
+                min: 0,
+                max: 15
+            }
+        )
+    );
+    assert_eq!(error("bcd 5"), (1, 5, ExpectedRegister("5".to_string())));
+    // no sign after the prefix
+    for number in ["0x-5", "0x+5", "0b-1", "--5"] {
+        assert_eq!(
+            error(&format!("v0 := {}", number)),
+            (1, 7, InvalidNumber(number.to_string()))
+        );
+    }
+    assert_eq!(assemble("v0 := -0x5").unwrap(), [0x60, 0xFB]);
+    assert_eq!(
+        error(": main\n\tjump nowhere"),
+        (2, 7, UndefinedLabel("nowhere".to_string()))
+    );
+    assert_eq!(error(": a\n: a"), (2, 3, Redefined("a".to_string())));
+    assert_eq!(
+        error(":alias x v1\n:const x 2"),
+        (2, 8, Redefined("x".to_string()))
+    );
+    // registers, numbers and keywords can't be names
+    for (source, column, name) in [
+        (":alias v1 v2", 8, "v1"),
+        (":alias VF v2", 8, "VF"),
+        (": v1", 3, "v1"),
+        (":const 0x10 3", 8, "0x10"),
+        (": jump", 3, "jump"),
+        (":alias end v0", 8, "end"),
+        (":const then 1", 8, "then"),
+        (": :main", 3, ":main"),
+    ] {
+        assert_eq!(
+            error(source),
+            (1, column, UnexpectedToken(name.to_string())),
+            "{}",
+            source
+        );
+    }
+    assert_eq!(
+        error("loop\n  v0 += 1 # no again"),
+        (1, 1, UnbalancedBlock("loop".to_string()))
+    );
+    assert_eq!(error("end"), (1, 1, UnbalancedBlock("end".to_string())));
+    assert_eq!(
+        error("if v0 == 1 v1 := 2"),
+        (1, 12, UnexpectedToken("v1".to_string()))
+    );
+    assert_eq!(error("v0 :="), (1, 6, UnexpectedEnd));
+    assert_eq!(error("then"), (1, 1, UnexpectedToken("then".to_string())));
+    // main past the reach of the jump to it
+    assert_eq!(
+        error(&format!(
+            ":byte 1\n{}\n: main exit",
+            ":byte 0 ".repeat(0xE00)
+        )),
+        (
+            3,
+            3,
+            OutOfRange {
+                value: 0x1003,
+                min: 0,
+                max: 0xFFF
+            }
+        )
+    );
+    assert_eq!(
+        assemble("jump 0x1000").unwrap_err().to_string(),
+        "line 1, column 6: 4096 is out of range, from 0 to 4095"
+    );
+}
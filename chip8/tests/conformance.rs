@@ -0,0 +1,562 @@
+//! Test roms run headless for a fixed number of cycles, checking the
+//! display against the golden framebuffers in `tests/golden`.
+//!
+//! The community roms are in `tests/roms`, so far only the IBM logo. The
+//! other roms are small ones written for these tests after the community
+//! suites (BC_test, corax+, Timendus' flags and quirks tests): each draws
+//! what it computed, so a wrong opcode shows up as a wrong digit. The
+//! values drawn are listed on each test, worked out by hand or from the
+//! quirks documented for each platform, and the goldens were checked
+//! against them. `UPDATE_GOLDEN=1 cargo test` rewrites the goldens from the
+//! current output, which then has to be checked the same way.
+//!
+//! TODO: bundle corax+, Timendus' flags and quirks tests and BC_test, with
+//! goldens of their documented pass screens.
+
+use std::env;
+use std::fs;
+
+use chip8::{Backend, Chip8, Platform, Quirks};
+
+const CYCLES: usize = 1000;
+
+/// Rom made of big-endian words, instructions or sprite data
+fn rom(words: &[u16]) -> Vec<u8> {
+    words.iter().flat_map(|w| w.to_be_bytes()).collect()
+}
+
+fn run(words: &[u16], platform: Platform, quirks: Quirks) -> Chip8 {
+    run_rom(&rom(words), platform, quirks)
+}
+
+fn run_rom(rom: &[u8], platform: Platform, quirks: Quirks) -> Chip8 {
+    let run_with = |backend| {
+        let mut c = Chip8::with_platform(platform, quirks)
+            .with_rng(0)
+            .with_backend(backend);
+        c.load_rom(rom).unwrap();
+        for _ in 0..CYCLES {
+            if let Err(err) = c.step() {
+                panic!("{}: {}", backend, err);
+            }
+        }
+        c
+    };
+    let c = run_with(Backend::Interpreter);
+    // both backends end in the same state
+    let cached = run_with(Backend::Cached);
+    assert!(c.save_state() == cached.save_state(), "the backends differ");
+    c
+}
+
+/// The display with '#' for lit pixels and '.' for the others
+fn ascii(c: &Chip8) -> String {
+    let mut s = String::new();
+    for row in c.gfx.chunks(c.width()) {
+        s.extend(row.iter().map(|p| if *p != 0 { '#' } else { '.' }));
+        s.push('\n');
+    }
+    s
+}
+
+/// The rows that differ, with the differing pixels marked below them
+fn diff(expected: &str, actual: &str) -> String {
+    let mut s = String::new();
+    let (expected, actual): (Vec<&str>, Vec<&str>) =
+        (expected.lines().collect(), actual.lines().collect());
+    for y in 0..expected.len().max(actual.len()) {
+        let (e, a) = (
+            expected.get(y).copied().unwrap_or(""),
+            actual.get(y).copied().unwrap_or(""),
+        );
+        if e == a {
+            continue;
+        }
+        let marks: String = (0..e.len().max(a.len()))
+            .map(|x| {
+                if e.get(x..x + 1) != a.get(x..x + 1) {
+                    '^'
+                } else {
+                    ' '
+                }
+            })
+            .collect();
+        s.push_str(&format!(
+            "row {:2} expected {}\n       actual   {}\n                {}\n",
+            y, e, a, marks
+        ));
+    }
+    s
+}
+
+fn check(name: &str, c: &Chip8) {
+    let path = format!("{}/tests/golden/{}.txt", env!("CARGO_MANIFEST_DIR"), name);
+    let actual = ascii(c);
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, &actual).unwrap();
+        return;
+    }
+    let expected = match fs::read_to_string(&path) {
+        Ok(expected) => expected,
+        Err(err) => panic!("{}: {}, UPDATE_GOLDEN=1 creates it", path, err),
+    };
+    if expected != actual {
+        panic!(
+            "display of {} differs from {}:\n{}",
+            name,
+            path,
+            diff(&expected, &actual)
+        );
+    }
+}
+
+/// The IBM logo rom: 00E0, 6XNN, ANNN, 7XNN, DXYN and 1NNN
+#[test]
+fn ibm_logo() {
+    let rom = include_bytes!("roms/ibm-logo.ch8");
+    check("ibm_logo", &run_rom(rom, Platform::Chip8, Quirks::modern()));
+}
+
+/// FX33 on 0, 7, 42, 100, 137 and 255, drawing the three digits of each
+#[test]
+fn bcd() {
+    #[rustfmt::skip]
+    let words = [
+        0x6300, // V3 = 0
+        0x6401, // V4 = 1
+        0x6A00, // VA = 0
+        0x2222, // call digits
+        0x6A07, // VA = 7
+        0x2222, // call digits
+        0x6A2A, // VA = 42
+        0x2222, // call digits
+        0x6300, // V3 = 0
+        0x6408, // V4 = 8
+        0x6A64, // VA = 100
+        0x2222, // call digits
+        0x6A89, // VA = 137
+        0x2222, // call digits
+        0x6AFF, // VA = 255
+        0x2222, // call digits
+        // end:
+        0x1220, // jump end
+        // digits:
+        0xA300, // I = 0x300
+        0xFA33, // BCD of VA at I
+        0xF265, // V0..V2 = I[0..3]
+        0xF029, // I = font V0
+        0xD345, // draw at V3, V4
+        0x7305, // V3 += 5
+        0xF129, // I = font V1
+        0xD345, // draw
+        0x7305, // V3 += 5
+        0xF229, // I = font V2
+        0xD345, // draw
+        0x7309, // V3 += 9
+        0x00EE, // return
+    ];
+    check("bcd", &run(&words, Platform::Chip8, Quirks::modern()));
+}
+
+/// 8XY4, 8XY5, 8XY6, 8XY7 and 8XYE, drawing the result in hex and VF for
+/// each. The last three use VF as VX, where the flag has to win over the
+/// result. Expected, row by row: 30 0, 10 1, 20 1 / 00 1, F0 0, 20 1 /
+/// 00 1, 02 1, 02 1 / 01 1, 00 0, 01 1
+#[test]
+fn flags() {
+    #[rustfmt::skip]
+    let words = [
+        0x6300, // V3 = 0
+        0x6400, // V4 = 0
+        0x6010, // V0 = 0x10
+        0x6120, // V1 = 0x20
+        0x8014, // 0x10 + 0x20
+        0x8500, // V5 = V0
+        0x86F0, // V6 = VF
+        0x229E, // call show
+        0x60F0, // V0 = 0xF0
+        0x6120, // V1 = 0x20
+        0x8014, // 0xF0 + 0x20, carry
+        0x8500, // V5 = V0
+        0x86F0, // V6 = VF
+        0x229E, // call show
+        0x6030, // V0 = 0x30
+        0x6110, // V1 = 0x10
+        0x8015, // 0x30 - 0x10
+        0x8500, // V5 = V0
+        0x86F0, // V6 = VF
+        0x229E, // call show
+        0x6300, // V3 = 0
+        0x6407, // V4 = 7
+        0x6010, // V0 = 0x10
+        0x6110, // V1 = 0x10
+        0x8015, // 0x10 - 0x10, no borrow
+        0x8500, // V5 = V0
+        0x86F0, // V6 = VF
+        0x229E, // call show
+        0x6010, // V0 = 0x10
+        0x6120, // V1 = 0x20
+        0x8015, // 0x10 - 0x20, borrow
+        0x8500, // V5 = V0
+        0x86F0, // V6 = VF
+        0x229E, // call show
+        0x6010, // V0 = 0x10
+        0x6130, // V1 = 0x30
+        0x8017, // 0x30 - 0x10
+        0x8500, // V5 = V0
+        0x86F0, // V6 = VF
+        0x229E, // call show
+        0x6300, // V3 = 0
+        0x640E, // V4 = 14
+        0x6010, // V0 = 0x10
+        0x6110, // V1 = 0x10
+        0x8017, // 0x10 - 0x10, no borrow
+        0x8500, // V5 = V0
+        0x86F0, // V6 = VF
+        0x229E, // call show
+        0x6005, // V0 = 0x05
+        0x8006, // 0x05 >> 1
+        0x8500, // V5 = V0
+        0x86F0, // V6 = VF
+        0x229E, // call show
+        0x6081, // V0 = 0x81
+        0x800E, // 0x81 << 1
+        0x8500, // V5 = V0
+        0x86F0, // V6 = VF
+        0x229E, // call show
+        0x6300, // V3 = 0
+        0x6415, // V4 = 21
+        0x6FF0, // VF = 0xF0
+        0x6120, // V1 = 0x20
+        0x8F14, // VF = 0xF0 + 0x20, the carry wins
+        0x85F0, // V5 = VF
+        0x86F0, // V6 = VF
+        0x229E, // call show
+        0x6F10, // VF = 0x10
+        0x6120, // V1 = 0x20
+        0x8F15, // VF = 0x10 - 0x20, the borrow wins
+        0x85F0, // V5 = VF
+        0x86F0, // V6 = VF
+        0x229E, // call show
+        0x6F10, // VF = 0x10
+        0x6130, // V1 = 0x30
+        0x8F17, // VF = 0x30 - 0x10, the borrow wins
+        0x85F0, // V5 = VF
+        0x86F0, // V6 = VF
+        0x229E, // call show
+        // end:
+        0x129C, // jump end
+        // show:
+        0x8250, // V2 = V5
+        0x8226, // V2 >>= 1
+        0x8226, // V2 >>= 1
+        0x8226, // V2 >>= 1
+        0x8226, // V2 >>= 1
+        0xF229, // I = font V2
+        0xD345, // draw at V3, V4
+        0x7305, // V3 += 5
+        0x6C0F, // VC = 0x0F
+        0x8250, // V2 = V5
+        0x82C2, // V2 &= VC
+        0xF229, // I = font V2
+        0xD345, // draw
+        0x7306, // V3 += 6
+        0xF629, // I = font V6
+        0xD345, // draw
+        0x7309, // V3 += 9
+        0x00EE, // return
+    ];
+    check("flags", &run(&words, Platform::Chip8, Quirks::modern()));
+}
+
+/// The conditional skips, drawing the number of each one that behaves,
+/// and E for those that don't: 0 to B when they all do. Key 9 is held
+#[test]
+fn skips() {
+    #[rustfmt::skip]
+    let words = [
+        0x6300, // V3 = 0
+        0x6401, // V4 = 1
+        0x6005, // V0 = 5
+        0x6105, // V1 = 5
+        0x6E00, // VE = 0
+        0x3005, // 3XNN equal, skips
+        0x6E0E, // VE = E
+        0xFE29, // I = font VE
+        0xD345, // draw at V3, V4
+        0x7305, // V3 += 5
+        0x6E0E, // VE = E
+        0x3006, // 3XNN not equal, no skip
+        0x6E01, // VE = 1
+        0xFE29, // I = font VE
+        0xD345, // draw at V3, V4
+        0x7305, // V3 += 5
+        0x6E02, // VE = 2
+        0x4006, // 4XNN not equal, skips
+        0x6E0E, // VE = E
+        0xFE29, // I = font VE
+        0xD345, // draw at V3, V4
+        0x7305, // V3 += 5
+        0x6E0E, // VE = E
+        0x4005, // 4XNN equal, no skip
+        0x6E03, // VE = 3
+        0xFE29, // I = font VE
+        0xD345, // draw at V3, V4
+        0x7305, // V3 += 5
+        0x6105, // V1 = 5
+        0x6E04, // VE = 4
+        0x5010, // 5XY0 equal, skips
+        0x6E0E, // VE = E
+        0xFE29, // I = font VE
+        0xD345, // draw at V3, V4
+        0x7305, // V3 += 5
+        0x6106, // V1 = 6
+        0x6E0E, // VE = E
+        0x5010, // 5XY0 not equal, no skip
+        0x6E05, // VE = 5
+        0xFE29, // I = font VE
+        0xD345, // draw at V3, V4
+        0x7305, // V3 += 5
+        0x6E06, // VE = 6
+        0x9010, // 9XY0 not equal, skips
+        0x6E0E, // VE = E
+        0xFE29, // I = font VE
+        0xD345, // draw at V3, V4
+        0x7305, // V3 += 5
+        0x6105, // V1 = 5
+        0x6E0E, // VE = E
+        0x9010, // 9XY0 equal, no skip
+        0x6E07, // VE = 7
+        0xFE29, // I = font VE
+        0xD345, // draw at V3, V4
+        0x7305, // V3 += 5
+        0x6203, // V2 = 3
+        0x6E0E, // VE = E
+        0xE29E, // EX9E key 3 up, no skip
+        0x6E08, // VE = 8
+        0xFE29, // I = font VE
+        0xD345, // draw at V3, V4
+        0x7305, // V3 += 5
+        0x6E09, // VE = 9
+        0xE2A1, // EXA1 key 3 up, skips
+        0x6E0E, // VE = E
+        0xFE29, // I = font VE
+        0xD345, // draw at V3, V4
+        0x7305, // V3 += 5
+        0x6209, // V2 = 9
+        0x6E0A, // VE = A
+        0xE29E, // EX9E key 9 down, skips
+        0x6E0E, // VE = E
+        0xFE29, // I = font VE
+        0xD345, // draw at V3, V4
+        0x7305, // V3 += 5
+        0x6E0E, // VE = E
+        0xE2A1, // EXA1 key 9 down, no skip
+        0x6E0B, // VE = B
+        0xFE29, // I = font VE
+        0xD345, // draw at V3, V4
+        0x7305, // V3 += 5
+        // end:
+        0x12A2, // jump end
+    ];
+    let mut c = Chip8::new().with_rng(0);
+    c.load_rom(&rom(&words)).unwrap();
+    c.key[9] = 1;
+    for _ in 0..CYCLES {
+        c.step().unwrap();
+    }
+    check("skips", &c);
+}
+
+/// Draws 1 or 0 for each quirk found: VY shifted, I incremented by
+/// FX55/FX65, BXNN, sprites clipped and VF reset by 8XY1. Expected: 11011
+/// on the VIP, 01110 on CHIP-48, 00110 on SUPER-CHIP, 11000 on XO-CHIP
+/// and 00000 with the modern quirks
+#[test]
+fn quirks() {
+    #[rustfmt::skip]
+    let words = [
+        0x6300, // V3 = 0
+        0x6401, // V4 = 1
+        0x6010, // V0 = 0x10
+        0x6104, // V1 = 0x04
+        0x8016, // V0 = V0 >> 1, or V1 >> 1
+        0x6E00, // VE = 0
+        0x4002, // skip if V0 != 2
+        0x6E01, // VE = 1
+        0x2264, // call show
+        0x6000, // V0 = 0
+        0xA300, // I = 0x300
+        0xF055, // store V0, I may move to 0x301
+        0x6001, // V0 = 1
+        0xF055, // store V0
+        0xA300, // I = 0x300
+        0xF065, // load V0
+        0x6E00, // VE = 0
+        0x4000, // skip if V0 != 0
+        0x6E01, // VE = 1
+        0x2264, // call show
+        0x6000, // V0 = 0
+        0x6202, // V2 = 2
+        0x6E01, // VE = 1
+        0xB230, // jump to target + V0, or + V2
+        // target:
+        0x6E00, // VE = 0
+        0x1234, // jump jumped
+        // jumped:
+        0x2264, // call show
+        0xA26C, // I = line
+        0x683C, // V8 = 60
+        0x691F, // V9 = 31
+        0xD891, // draw 8 pixels at 60, 31
+        0xA26E, // I = dot
+        0x6800, // V8 = 0
+        0xD891, // draw a pixel at 0, 31
+        0x85F0, // V5 = VF
+        0xD891, // erase it
+        0xA26C, // I = line
+        0x683C, // V8 = 60
+        0xD891, // erase the line
+        0x6E01, // VE = 1
+        0x3500, // skip if V5 == 0
+        0x6E00, // VE = 0
+        0x2264, // call show
+        0x6F05, // VF = 5
+        0x8011, // V0 |= V1
+        0x6E01, // VE = 1
+        0x3F00, // skip if VF == 0
+        0x6E00, // VE = 0
+        0x2264, // call show
+        // end:
+        0x1262, // jump end
+        // show:
+        0xFE29, // I = font VE
+        0xD345, // draw at V3, V4
+        0x7305, // V3 += 5
+        0x00EE, // return
+        // line:
+        0xFF00, // sprite data
+        // dot:
+        0x8000, // sprite data
+    ];
+    for (name, quirks) in [
+        ("quirks_vip", Quirks::vip()),
+        ("quirks_chip48", Quirks::chip48()),
+        ("quirks_schip", Quirks::schip()),
+        ("quirks_xochip", Quirks::xochip()),
+        ("quirks_modern", Quirks::modern()),
+    ] {
+        check(name, &run(&words, Platform::Chip8, quirks));
+    }
+}
+
+/// DXYN overlapping, wrapping around the edges and starting off screen,
+/// then VF of the first two sprites: 0 and 1
+#[test]
+fn draw() {
+    #[rustfmt::skip]
+    let words = [
+        0xA23C, // I = checker
+        0x6002, // V0 = 2
+        0x6102, // V1 = 2
+        0xD018, // draw at 2, 2
+        0x86F0, // V6 = VF
+        0x6006, // V0 = 6
+        0x6106, // V1 = 6
+        0xD018, // draw at 6, 6, over the first one
+        0x87F0, // V7 = VF
+        0x603E, // V0 = 62
+        0x610A, // V1 = 10
+        0xD018, // draw at 62, 10, wraps to the left edge
+        0x6042, // V0 = 66
+        0x6128, // V1 = 40
+        0xD018, // draw at 66, 40, starts at 2, 8
+        0xA244, // I = c8
+        0x6018, // V0 = 24
+        0x6104, // V1 = 4
+        0xD01F, // draw 15 rows at 24, 4
+        0x6020, // V0 = 32
+        0xA253, // I = c8 + 15
+        0xD01F, // draw 15 rows at 32, 4
+        0x6030, // V0 = 48
+        0x611A, // V1 = 26
+        0xF629, // I = font V6
+        0xD015, // draw VF of the first sprite
+        0x6036, // V0 = 54
+        0xF729, // I = font V7
+        0xD015, // draw VF of the second sprite
+        // end:
+        0x123A, // jump end
+        // checker:
+        0xAA55, // sprite data
+        0xAA55,
+        0xAA55,
+        0xAA55,
+        // c8:
+        0x3C7E, // C and 8, 15 rows each
+        0xE7C3,
+        0xC0C0,
+        0xC0C0,
+        0xC0C0,
+        0xC0C3,
+        0xE77E,
+        0x3C3C,
+        0x7EE7,
+        0xC3C3,
+        0xE77E,
+        0x7EE7,
+        0xC3C3,
+        0xC3E7,
+        0x7E3C,
+    ];
+    check("draw", &run(&words, Platform::Chip8, Quirks::modern()));
+}
+
+/// SUPER-CHIP hi-res: the big font, a 16x16 sprite, scrolling down and
+/// right, and a 16x16 sprite clipped at the bottom right corner
+#[test]
+fn hires() {
+    #[rustfmt::skip]
+    let words = [
+        0x00FF, // hi-res
+        0x6300, // V3 = 0
+        0x6402, // V4 = 2
+        0x6200, // V2 = 0
+        // digit:
+        0xF230, // I = big font V2
+        0xD34A, // draw 8x10 at V3, V4
+        0x730A, // V3 += 10
+        0x7201, // V2 += 1
+        0x320A, // skip if V2 == 10
+        0x1208, // jump digit
+        0xA228, // I = frame
+        0x6330, // V3 = 48
+        0x6414, // V4 = 20
+        0xD340, // draw 16x16 at 48, 20
+        0x00C4, // scroll down 4
+        0x00FB, // scroll right 4
+        0x6378, // V3 = 120
+        0x6432, // V4 = 50
+        0xD340, // draw 16x16 at 120, 50, clipped
+        // end:
+        0x1226, // jump end
+        // frame:
+        0xFFFF, // 16x16 frame
+        0x8001,
+        0x8001,
+        0x8001,
+        0x8001,
+        0x8001,
+        0x8001,
+        0x8001,
+        0x8001,
+        0x8001,
+        0x8001,
+        0x8001,
+        0x8001,
+        0x8001,
+        0x8001,
+        0xFFFF,
+    ];
+    check("hires", &run(&words, Platform::SuperChip, Quirks::schip()));
+}
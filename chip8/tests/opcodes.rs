@@ -0,0 +1,624 @@
+//! One instruction at a time: each test sets up the machine with the
+//! builder methods, executes a single opcode and checks the state it
+//! leaves, with the edge cases of carries, borrows, wrap-around and
+//! clipping.
+
+use chip8::{Chip8, CpuError, Platform, Quirks, StepOutcome};
+
+fn chip8() -> Chip8 {
+    Chip8::new().with_rng(0)
+}
+
+fn schip() -> Chip8 {
+    Chip8::with_platform(Platform::SuperChip, Quirks::schip()).with_rng(0)
+}
+
+fn xochip() -> Chip8 {
+    Chip8::with_platform(Platform::XoChip, Quirks::xochip()).with_rng(0)
+}
+
+/// Executes `opcode`, which has to succeed
+fn exec(c: Chip8, opcode: u16) -> Chip8 {
+    let mut c = c.with_opcode(opcode);
+    if let Err(err) = c.step() {
+        panic!("{:04X}: {}", opcode, err);
+    }
+    c
+}
+
+/// Executes `opcode`, which has to fail, and checks that the machine was
+/// left as it was
+fn exec_err(c: Chip8, opcode: u16) -> CpuError {
+    let mut c = c.with_opcode(opcode);
+    let (pc, v, index) = (c.pc(), *c.v(), c.index());
+    let err = c.step().unwrap_err();
+    assert_eq!((c.pc(), *c.v(), c.index()), (pc, v, index));
+    err
+}
+
+fn unknown(opcode: u16) -> CpuError {
+    CpuError::UnknownOpcode { pc: 0x200, opcode }
+}
+
+/// Coordinates of the lit pixels
+fn lit(c: &Chip8) -> Vec<(usize, usize)> {
+    let w = c.width();
+    (0..c.gfx.len())
+        .filter(|i| c.gfx[*i] != 0)
+        .map(|i| (i % w, i / w))
+        .collect()
+}
+
+#[test]
+fn cls_00e0() {
+    let c = exec(chip8().with_pixel(3, 4, 1), 0x00E0);
+    assert!(lit(&c).is_empty());
+    assert!(c.draw_flag);
+    assert_eq!(c.pc(), 0x202);
+
+    // only the selected plane is cleared
+    let c = exec(xochip().with_pixel(3, 4, 3).with_planes(1), 0x00E0);
+    assert_eq!(c.gfx[4 * 64 + 3], 2);
+}
+
+#[test]
+fn ret_00ee() {
+    let c = exec(chip8().with_stack(&[0x300, 0x400]), 0x00EE);
+    assert_eq!(c.pc(), 0x402);
+    assert_eq!(c.sp(), 1);
+
+    assert_eq!(
+        exec_err(chip8(), 0x00EE),
+        CpuError::StackUnderflow { pc: 0x200 }
+    );
+}
+
+#[test]
+fn sys_0nnn() {
+    // machine code routines of the original interpreter can't run
+    assert_eq!(exec_err(chip8(), 0x0123), unknown(0x0123));
+    assert_eq!(exec_err(chip8(), 0x00E1), unknown(0x00E1));
+    assert_eq!(exec_err(chip8(), 0x0000), unknown(0x0000));
+}
+
+#[test]
+fn jump_1nnn() {
+    assert_eq!(exec(chip8(), 0x1ABC).pc(), 0xABC);
+}
+
+#[test]
+fn call_2nnn() {
+    let c = exec(chip8().with_stack(&[0x300]), 0x2ABC);
+    assert_eq!(c.pc(), 0xABC);
+    assert_eq!(c.sp(), 2);
+    assert_eq!(&c.stack()[..2], &[0x300, 0x200]);
+
+    assert_eq!(
+        exec_err(chip8().with_stack(&[0x300; 16]), 0x2ABC),
+        CpuError::StackOverflow { pc: 0x200 }
+    );
+}
+
+#[test]
+fn skip_eq_3xnn() {
+    assert_eq!(exec(chip8().with_v(3, 0x42), 0x3342).pc(), 0x204);
+    assert_eq!(exec(chip8().with_v(3, 0x42), 0x3343).pc(), 0x202);
+
+    // XO-CHIP skips the 4 bytes of F000 NNNN
+    let c = xochip()
+        .with_v(3, 0x42)
+        .with_memory(0x202, &[0xF0, 0x00, 0x12, 0x34]);
+    assert_eq!(exec(c, 0x3342).pc(), 0x206);
+    // but not on the other platforms, where F000 is unknown
+    let c = chip8()
+        .with_v(3, 0x42)
+        .with_memory(0x202, &[0xF0, 0x00, 0x12, 0x34]);
+    assert_eq!(exec(c, 0x3342).pc(), 0x204);
+}
+
+#[test]
+fn skip_ne_4xnn() {
+    assert_eq!(exec(chip8().with_v(3, 0x42), 0x4342).pc(), 0x202);
+    assert_eq!(exec(chip8().with_v(3, 0x42), 0x4343).pc(), 0x204);
+}
+
+#[test]
+fn skip_eq_5xy0() {
+    assert_eq!(exec(chip8().with_v(1, 7).with_v(2, 7), 0x5120).pc(), 0x204);
+    assert_eq!(exec(chip8().with_v(1, 7).with_v(2, 8), 0x5120).pc(), 0x202);
+    // 5XY2 and 5XY3 are XO-CHIP only
+    assert_eq!(exec_err(chip8(), 0x5121), unknown(0x5121));
+    assert_eq!(exec_err(chip8(), 0x5122), unknown(0x5122));
+}
+
+#[test]
+fn load_6xnn() {
+    assert_eq!(exec(chip8(), 0x6AFF).v()[0xA], 0xFF);
+}
+
+#[test]
+fn add_7xnn() {
+    let c = exec(chip8().with_v(1, 0xFF).with_v(0xF, 7), 0x7102);
+    assert_eq!(c.v()[1], 0x01);
+    // no carry flag
+    assert_eq!(c.v()[0xF], 7);
+}
+
+#[test]
+fn logic_8xy1_8xy2_8xy3() {
+    for (opcode, result) in [(0x8011, 0b1110), (0x8012, 0b1000), (0x8013, 0b0110)] {
+        let c = chip8().with_v(0, 0b1100).with_v(1, 0b1010).with_v(0xF, 7);
+        let c = exec(c, opcode);
+        assert_eq!(c.v()[0], result, "{:04X}", opcode);
+        assert_eq!(c.v()[0xF], 7, "{:04X}", opcode);
+
+        let c = Chip8::with_quirks(Quirks::vip()).with_v(0xF, 7);
+        assert_eq!(exec(c, opcode).v()[0xF], 0, "{:04X} resets VF", opcode);
+    }
+}
+
+#[test]
+fn load_8xy0() {
+    let c = exec(chip8().with_v(1, 0x42).with_v(0xF, 7), 0x8010);
+    assert_eq!(c.v()[0], 0x42);
+    assert_eq!(c.v()[0xF], 7);
+}
+
+#[test]
+fn add_8xy4() {
+    let c = exec(chip8().with_v(0, 0xFE).with_v(1, 0x01), 0x8014);
+    assert_eq!((c.v()[0], c.v()[0xF]), (0xFF, 0));
+    let c = exec(chip8().with_v(0, 0xFF).with_v(1, 0x01), 0x8014);
+    assert_eq!((c.v()[0], c.v()[0xF]), (0x00, 1));
+    let c = exec(chip8().with_v(0, 0xFF).with_v(1, 0xFF), 0x8014);
+    assert_eq!((c.v()[0], c.v()[0xF]), (0xFE, 1));
+    // with VF as VX the flag wins over the sum
+    let c = exec(chip8().with_v(0xF, 0xFF).with_v(1, 0x01), 0x8F14);
+    assert_eq!(c.v()[0xF], 1);
+    // VF as VY is read before being set
+    let c = exec(chip8().with_v(0, 0x01).with_v(0xF, 0xFF), 0x80F4);
+    assert_eq!((c.v()[0], c.v()[0xF]), (0x00, 1));
+}
+
+#[test]
+fn sub_8xy5() {
+    let c = exec(chip8().with_v(0, 0x05).with_v(1, 0x03), 0x8015);
+    assert_eq!((c.v()[0], c.v()[0xF]), (0x02, 1));
+    // equal values don't borrow
+    let c = exec(chip8().with_v(0, 0x05).with_v(1, 0x05), 0x8015);
+    assert_eq!((c.v()[0], c.v()[0xF]), (0x00, 1));
+    let c = exec(chip8().with_v(0, 0x03).with_v(1, 0x05), 0x8015);
+    assert_eq!((c.v()[0], c.v()[0xF]), (0xFE, 0));
+    let c = exec(chip8().with_v(0xF, 0x03).with_v(1, 0x05), 0x8F15);
+    assert_eq!(c.v()[0xF], 0);
+}
+
+#[test]
+fn subn_8xy7() {
+    let c = exec(chip8().with_v(0, 0x03).with_v(1, 0x05), 0x8017);
+    assert_eq!((c.v()[0], c.v()[0xF]), (0x02, 1));
+    let c = exec(chip8().with_v(0, 0x05).with_v(1, 0x05), 0x8017);
+    assert_eq!((c.v()[0], c.v()[0xF]), (0x00, 1));
+    let c = exec(chip8().with_v(0, 0x05).with_v(1, 0x03), 0x8017);
+    assert_eq!((c.v()[0], c.v()[0xF]), (0xFE, 0));
+    let c = exec(chip8().with_v(0xF, 0x03).with_v(1, 0x05), 0x8F17);
+    assert_eq!(c.v()[0xF], 1);
+}
+
+#[test]
+fn shr_8xy6() {
+    let c = exec(chip8().with_v(0, 0x05).with_v(1, 0x80), 0x8016);
+    assert_eq!((c.v()[0], c.v()[0xF]), (0x02, 1));
+    let c = exec(chip8().with_v(0, 0x04), 0x8016);
+    assert_eq!((c.v()[0], c.v()[0xF]), (0x02, 0));
+    // the VIP shifts VY into VX
+    let c = Chip8::with_quirks(Quirks::vip())
+        .with_v(0, 0x05)
+        .with_v(1, 0x80);
+    let c = exec(c, 0x8016);
+    assert_eq!((c.v()[0], c.v()[0xF]), (0x40, 0));
+    let c = exec(chip8().with_v(0xF, 0x05), 0x8F06);
+    assert_eq!(c.v()[0xF], 1);
+}
+
+#[test]
+fn shl_8xye() {
+    let c = exec(chip8().with_v(0, 0x81).with_v(1, 0x01), 0x801E);
+    assert_eq!((c.v()[0], c.v()[0xF]), (0x02, 1));
+    let c = exec(chip8().with_v(0, 0x40), 0x801E);
+    assert_eq!((c.v()[0], c.v()[0xF]), (0x80, 0));
+    let c = Chip8::with_quirks(Quirks::vip())
+        .with_v(0, 0x81)
+        .with_v(1, 0x01);
+    let c = exec(c, 0x801E);
+    assert_eq!((c.v()[0], c.v()[0xF]), (0x02, 0));
+    let c = exec(chip8().with_v(0xF, 0x40), 0x8F0E);
+    assert_eq!(c.v()[0xF], 0);
+}
+
+#[test]
+fn unknown_8xyn() {
+    for n in [0x8, 0x9, 0xA, 0xB, 0xC, 0xD, 0xF] {
+        assert_eq!(exec_err(chip8(), 0x8010 | n), unknown(0x8010 | n));
+    }
+}
+
+#[test]
+fn skip_ne_9xy0() {
+    assert_eq!(exec(chip8().with_v(1, 7).with_v(2, 8), 0x9120).pc(), 0x204);
+    assert_eq!(exec(chip8().with_v(1, 7).with_v(2, 7), 0x9120).pc(), 0x202);
+    assert_eq!(exec_err(chip8(), 0x9121), unknown(0x9121));
+}
+
+#[test]
+fn load_i_annn() {
+    assert_eq!(exec(chip8(), 0xAFED).index(), 0xFED);
+}
+
+#[test]
+fn jump_v0_bnnn() {
+    let c = chip8().with_v(0, 0x10).with_v(1, 0x20);
+    assert_eq!(exec(c, 0xB123).pc(), 0x133);
+    // SUPER-CHIP reads BXNN
+    let c = schip().with_v(0, 0x10).with_v(1, 0x20);
+    assert_eq!(exec(c, 0xB123).pc(), 0x143);
+}
+
+#[test]
+fn random_cxnn() {
+    assert_eq!(exec(chip8().with_v(0, 0xFF), 0xC000).v()[0], 0);
+    for seed in 0..32 {
+        let c = exec(Chip8::new().with_rng(seed), 0xC00F);
+        assert!(c.v()[0] <= 0x0F);
+    }
+    // the same seed gives the same number
+    let a = exec(Chip8::new().with_rng(9), 0xC0FF);
+    let b = exec(Chip8::new().with_rng(9), 0xC0FF);
+    assert_eq!(a.v()[0], b.v()[0]);
+}
+
+#[test]
+fn draw_dxyn() {
+    // the font 0 is at address 0
+    let c = exec(chip8().with_v(0, 1).with_v(1, 2), 0xD015);
+    let mut zero = vec![];
+    for (y, row) in [0xF0, 0x90, 0x90, 0x90, 0xF0].iter().enumerate() {
+        for x in 0..8 {
+            if row & 0x80 >> x != 0 {
+                zero.push((1 + x, 2 + y));
+            }
+        }
+    }
+    zero.sort_by_key(|(x, y)| (*y, *x));
+    assert_eq!(lit(&c), zero);
+    assert_eq!(c.v()[0xF], 0);
+    assert!(c.draw_flag);
+    assert_eq!(c.index(), 0);
+
+    // drawing it again erases it and sets VF
+    let c = exec(c.with_pc(0x200), 0xD015);
+    assert!(lit(&c).is_empty());
+    assert_eq!(c.v()[0xF], 1);
+
+    // the coordinates wrap around
+    let c = exec(chip8().with_v(0, 64 + 1).with_v(1, 32 + 2), 0xD015);
+    assert_eq!(lit(&c), zero);
+}
+
+#[test]
+fn draw_dxyn_edges() {
+    // an 8 pixel line at 60, 31
+    let line = |c: Chip8| {
+        let c = c
+            .with_memory(0x300, &[0xFF])
+            .with_index(0x300)
+            .with_v(0, 60)
+            .with_v(1, 31);
+        exec(c, 0xD011)
+    };
+    let c = line(chip8());
+    let wrapped = vec![(0, 31), (1, 31), (2, 31), (3, 31)];
+    let clipped = vec![(60, 31), (61, 31), (62, 31), (63, 31)];
+    assert_eq!(lit(&c), [wrapped, clipped.clone()].concat());
+    let c = line(Chip8::with_quirks(Quirks::vip()));
+    assert_eq!(lit(&c), clipped);
+
+    // rows past the bottom edge
+    let c = chip8()
+        .with_memory(0x300, &[0x80, 0x80])
+        .with_index(0x300)
+        .with_v(1, 31);
+    assert_eq!(lit(&exec(c, 0xD012)), vec![(0, 0), (0, 31)]);
+    let c = Chip8::with_quirks(Quirks::vip())
+        .with_memory(0x300, &[0x80, 0x80])
+        .with_index(0x300)
+        .with_v(1, 31);
+    assert_eq!(lit(&exec(c, 0xD012)), vec![(0, 31)]);
+
+    // sprite data past the end of memory
+    assert_eq!(
+        exec_err(chip8().with_index(0xFFE), 0xD013),
+        CpuError::MemoryOutOfBounds { addr: 0x1000 }
+    );
+}
+
+#[test]
+fn skip_key_ex9e_exa1() {
+    // only the low nibble of VX selects the key
+    let c = chip8().with_v(0, 0x15).with_key(5);
+    assert_eq!(exec(c.clone(), 0xE09E).pc(), 0x204);
+    assert_eq!(exec(c, 0xE0A1).pc(), 0x202);
+    let c = chip8().with_v(0, 0x05).with_key(6);
+    assert_eq!(exec(c.clone(), 0xE09E).pc(), 0x202);
+    assert_eq!(exec(c, 0xE0A1).pc(), 0x204);
+    assert_eq!(exec_err(chip8(), 0xE09F), unknown(0xE09F));
+}
+
+#[test]
+fn timers_fx07_fx15_fx18() {
+    let c = exec(chip8().with_timers(0x20, 0x30), 0xF007);
+    assert_eq!(c.v()[0], 0x20);
+    let c = exec(chip8().with_v(3, 0x40), 0xF315);
+    assert_eq!(c.delay_timer(), 0x40);
+    let c = exec(chip8().with_v(3, 0x50), 0xF318);
+    assert_eq!(c.sound_timer(), 0x50);
+    assert!(c.is_sound_active());
+}
+
+#[test]
+fn wait_key_fx0a() {
+    let mut c = chip8().with_opcode(0xF30A);
+    assert_eq!(c.step(), Ok(StepOutcome::WaitingForKey));
+    assert_eq!(c.pc(), 0x200);
+    let c = exec(c.with_key(0xB), 0xF30A);
+    assert_eq!(c.v()[3], 0xB);
+    assert_eq!(c.pc(), 0x202);
+}
+
+#[test]
+fn add_i_fx1e() {
+    let c = exec(
+        chip8().with_index(0xFFE).with_v(0, 3).with_v(0xF, 7),
+        0xF01E,
+    );
+    assert_eq!(c.index(), 0x1001);
+    assert_eq!(c.v()[0xF], 7);
+    let c = exec(chip8().with_index(0xFFFF).with_v(0, 2), 0xF01E);
+    assert_eq!(c.index(), 0x0001);
+}
+
+#[test]
+fn font_fx29() {
+    assert_eq!(exec(chip8().with_v(0, 0xA), 0xF029).index(), 50);
+    assert_eq!(exec(chip8().with_v(0, 0xF), 0xF029).index(), 75);
+}
+
+#[test]
+fn bcd_fx33() {
+    for (value, digits) in [
+        (0, [0, 0, 0]),
+        (9, [0, 0, 9]),
+        (10, [0, 1, 0]),
+        (99, [0, 9, 9]),
+        (100, [1, 0, 0]),
+        (255, [2, 5, 5]),
+    ] {
+        let c = exec(chip8().with_v(4, value).with_index(0x300), 0xF433);
+        assert_eq!(&c.memory()[0x300..0x303], &digits, "{}", value);
+        assert_eq!(c.index(), 0x300);
+    }
+    assert_eq!(
+        exec_err(chip8().with_index(0xFFE), 0xF033),
+        CpuError::MemoryOutOfBounds { addr: 0x1000 }
+    );
+}
+
+#[test]
+fn store_load_fx55_fx65() {
+    let c = chip8()
+        .with_v(0, 1)
+        .with_v(1, 2)
+        .with_v(2, 3)
+        .with_v(3, 4)
+        .with_index(0x300);
+    let c = exec(c, 0xF255);
+    assert_eq!(&c.memory()[0x300..0x304], &[1, 2, 3, 0]);
+    assert_eq!(c.index(), 0x300);
+
+    let c = chip8().with_memory(0x300, &[5, 6, 7]).with_index(0x300);
+    let c = exec(c, 0xF165);
+    assert_eq!(&c.v()[..3], &[5, 6, 0]);
+    assert_eq!(c.index(), 0x300);
+
+    // the VIP leaves I after the last register
+    let c = Chip8::with_quirks(Quirks::vip()).with_index(0x300);
+    assert_eq!(exec(c.clone(), 0xF255).index(), 0x303);
+    assert_eq!(exec(c, 0xF065).index(), 0x301);
+
+    assert_eq!(
+        exec_err(chip8().with_index(0xFFE), 0xF255),
+        CpuError::MemoryOutOfBounds { addr: 0x1000 }
+    );
+    assert_eq!(
+        exec_err(chip8().with_index(0xFFE), 0xF265),
+        CpuError::MemoryOutOfBounds { addr: 0x1000 }
+    );
+}
+
+#[test]
+fn unknown_fxnn() {
+    for opcode in [0xF000, 0xF001, 0xF030, 0xF03A, 0xF075, 0xF0FF] {
+        assert_eq!(exec_err(chip8(), opcode), unknown(opcode));
+    }
+}
+
+#[test]
+fn schip_scroll_00cn_00fb_00fc() {
+    let c = exec(schip().with_pixel(0, 0, 1), 0x00C2);
+    assert_eq!(lit(&c), vec![(0, 2)]);
+    // pixels scrolled off the bottom are lost
+    let c = exec(schip().with_pixel(5, 31, 1), 0x00C1);
+    assert!(lit(&c).is_empty());
+    let c = exec(schip().with_pixel(0, 0, 1), 0x00FB);
+    assert_eq!(lit(&c), vec![(4, 0)]);
+    let c = exec(schip().with_pixel(6, 0, 1), 0x00FC);
+    assert_eq!(lit(&c), vec![(2, 0)]);
+    let c = exec(schip().with_pixel(2, 0, 1), 0x00FC);
+    assert!(lit(&c).is_empty());
+    assert_eq!(exec_err(chip8(), 0x00C2), unknown(0x00C2));
+    assert_eq!(exec_err(chip8(), 0x00FB), unknown(0x00FB));
+}
+
+#[test]
+fn schip_exit_00fd() {
+    let mut c = schip().with_opcode(0x00FD);
+    assert_eq!(c.step(), Ok(StepOutcome::Exit));
+    assert_eq!(c.pc(), 0x200);
+}
+
+#[test]
+fn schip_resolution_00fe_00ff() {
+    let c = exec(schip().with_pixel(1, 1, 1), 0x00FF);
+    assert_eq!((c.width(), c.height()), (128, 64));
+    assert_eq!(c.gfx.len(), 128 * 64);
+    assert!(lit(&c).is_empty());
+    let c = exec(c, 0x00FE);
+    assert_eq!((c.width(), c.height()), (64, 32));
+    assert_eq!(exec_err(chip8(), 0x00FF), unknown(0x00FF));
+}
+
+#[test]
+fn schip_draw_dxy0() {
+    let c = schip()
+        .with_hires(true)
+        .with_memory(0x300, &[0xFF; 32])
+        .with_index(0x300)
+        .with_v(0, 8)
+        .with_v(1, 8);
+    let c = exec(c, 0xD010);
+    assert_eq!(lit(&c).len(), 16 * 16);
+    assert_eq!(lit(&c)[0], (8, 8));
+    assert_eq!(lit(&c)[255], (23, 23));
+    // a 16x16 sprite clipped at the bottom right corner
+    let c = schip()
+        .with_hires(true)
+        .with_memory(0x300, &[0xFF; 32])
+        .with_index(0x300)
+        .with_v(0, 120)
+        .with_v(1, 60);
+    assert_eq!(lit(&exec(c, 0xD010)).len(), 8 * 4);
+    // on CHIP-8 DXY0 draws nothing
+    let c = exec(chip8().with_index(0x300), 0xD010);
+    assert!(lit(&c).is_empty());
+}
+
+#[test]
+fn schip_big_font_fx30() {
+    assert_eq!(exec(schip().with_v(0, 2), 0xF030).index(), 0x50 + 2 * 10);
+}
+
+#[test]
+fn schip_flags_fx75_fx85() {
+    let c = exec(schip().with_v(0, 1).with_v(1, 2).with_v(2, 3), 0xF175);
+    let c = exec(
+        c.with_v(0, 0).with_v(1, 0).with_v(2, 0).with_pc(0x200),
+        0xF285,
+    );
+    // only V0 and V1 were saved
+    assert_eq!(&c.v()[..3], &[1, 2, 0]);
+}
+
+#[test]
+fn xochip_scroll_up_00dn() {
+    let c = exec(xochip().with_pixel(0, 5, 1), 0x00D2);
+    assert_eq!(lit(&c), vec![(0, 3)]);
+    assert_eq!(exec_err(schip(), 0x00D2), unknown(0x00D2));
+}
+
+#[test]
+fn xochip_save_load_range_5xy2_5xy3() {
+    let c = xochip()
+        .with_v(1, 1)
+        .with_v(2, 2)
+        .with_v(3, 3)
+        .with_index(0x300);
+    let c = exec(c, 0x5132);
+    assert_eq!(&c.memory()[0x300..0x303], &[1, 2, 3]);
+    assert_eq!(c.index(), 0x300);
+    // descending when X > Y
+    let c = exec(c.with_pc(0x200), 0x5312);
+    assert_eq!(&c.memory()[0x300..0x303], &[3, 2, 1]);
+
+    let c = xochip().with_memory(0x300, &[7, 8]).with_index(0x300);
+    let c = exec(c, 0x5563);
+    assert_eq!(&c.v()[5..7], &[7, 8]);
+    let c = exec(c.with_pc(0x200), 0x5653);
+    assert_eq!(&c.v()[5..7], &[8, 7]);
+}
+
+#[test]
+fn xochip_load_long_i_f000() {
+    let c = xochip().with_memory(0x202, &[0xAB, 0xCD]);
+    let c = exec(c, 0xF000);
+    assert_eq!(c.index(), 0xABCD);
+    assert_eq!(c.pc(), 0x204);
+}
+
+#[test]
+fn xochip_planes_fn01() {
+    assert_eq!(exec(xochip(), 0xF201).planes(), 2);
+    assert_eq!(exec(xochip(), 0xF301).planes(), 3);
+    assert_eq!(exec(xochip(), 0xF001).planes(), 0);
+}
+
+#[test]
+fn xochip_draw_planes() {
+    // with both planes selected, the data of the second follows the first
+    let c = xochip()
+        .with_planes(3)
+        .with_memory(0x300, &[0x80, 0x40])
+        .with_index(0x300);
+    let c = exec(c, 0xD001);
+    assert_eq!(c.gfx[0], 1);
+    assert_eq!(c.gfx[1], 2);
+    // nothing is drawn with no plane selected
+    let c = xochip().with_planes(0).with_index(0x300);
+    assert!(lit(&exec(c, 0xD001)).is_empty());
+}
+
+#[test]
+fn xochip_audio_f002_fx3a() {
+    // a square wave until a pattern is loaded
+    assert_eq!(xochip().audio_pattern()[7..9], [0xFF, 0x00]);
+    let pattern: Vec<u8> = (0..16).collect();
+    let c = xochip().with_memory(0x300, &pattern).with_index(0x300);
+    assert_eq!(&exec(c, 0xF002).audio_pattern()[..], &pattern[..]);
+    assert_eq!(exec(xochip().with_v(4, 100), 0xF43A).pitch(), 100);
+}
+
+#[test]
+fn xochip_top_of_memory() {
+    let past_end = CpuError::MemoryOutOfBounds { addr: 0x10000 };
+    // the last instruction of memory can't move pc on
+    assert_eq!(exec_err(xochip().with_pc(0xFFFE), 0x6000), past_end);
+    let c = exec(xochip().with_pc(0xFFFC), 0x6042);
+    assert_eq!((c.pc(), c.v()[0]), (0xFFFE, 0x42));
+    // but it can jump away
+    assert_eq!(exec(xochip().with_pc(0xFFFE), 0x1300).pc(), 0x300);
+
+    // a skip over F000 NNNN at the end of memory
+    let c = xochip()
+        .with_pc(0xFFFA)
+        .with_memory(0xFFFC, &[0xF0, 0x00, 0x12, 0x34]);
+    assert_eq!(exec_err(c.clone(), 0x3000), past_end);
+    assert_eq!(exec(c, 0x3001).pc(), 0xFFFC);
+    assert_eq!(
+        exec_err(xochip().with_pc(0xFFFC), 0xF000),
+        CpuError::MemoryOutOfBounds { addr: 0x10000 }
+    );
+
+    // a return to a call made from the last instruction
+    let c = xochip().with_stack(&[0xFFFE]);
+    assert_eq!(exec_err(c.clone(), 0x00EE), past_end);
+    assert_eq!(c.sp(), 1);
+}
@@ -0,0 +1,103 @@
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+
+use chip8::{Chip8, Platform};
+
+pub struct AudioOptions {
+    /// 0 to 100
+    pub volume: u8,
+    /// Frequency of the square wave, in Hz
+    pub frequency: f32,
+    pub mute: bool,
+}
+
+struct Wave {
+    sample_rate: f32,
+    frequency: f32,
+    volume: f32,
+    phase: f32,
+    // XO-CHIP pattern and its playback rate in bits per second
+    pattern: Option<([u8; 16], f32)>,
+}
+
+impl AudioCallback for Wave {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            let high = match self.pattern {
+                Some((pattern, rate)) => {
+                    let bit = self.phase as usize % 128;
+                    self.phase = (self.phase + rate / self.sample_rate) % 128.0;
+                    pattern[bit / 8] & (0x80 >> (bit % 8)) != 0
+                }
+                None => {
+                    self.phase = (self.phase + self.frequency / self.sample_rate) % 1.0;
+                    self.phase < 0.5
+                }
+            };
+            *sample = if high { self.volume } else { -self.volume };
+        }
+    }
+}
+
+/// Plays the CHIP-8 buzzer while the sound timer is non-zero. When no audio
+/// device is available it stays silent instead of failing
+pub struct Buzzer {
+    device: Option<AudioDevice<Wave>>,
+    playing: bool,
+}
+
+impl Buzzer {
+    pub fn new(sdl_context: &sdl2::Sdl, opts: &AudioOptions) -> Buzzer {
+        let device = if opts.mute {
+            None
+        } else {
+            match Buzzer::open(sdl_context, opts) {
+                Ok(device) => Some(device),
+                Err(err) => {
+                    eprintln!("Audio disabled: {}", err);
+                    None
+                }
+            }
+        };
+        Buzzer {
+            device,
+            playing: false,
+        }
+    }
+    fn open(sdl_context: &sdl2::Sdl, opts: &AudioOptions) -> Result<AudioDevice<Wave>, String> {
+        let audio_subsystem = sdl_context.audio()?;
+        let desired = AudioSpecDesired {
+            freq: Some(44100),
+            channels: Some(1),
+            samples: None,
+        };
+        audio_subsystem.open_playback(None, &desired, |spec| Wave {
+            sample_rate: spec.freq as f32,
+            frequency: opts.frequency,
+            volume: opts.volume.min(100) as f32 / 100.0,
+            phase: 0.0,
+            pattern: None,
+        })
+    }
+    /// Starts or stops the sound following the state of the machine
+    pub fn update(&mut self, chip8: &Chip8) {
+        let device = match &mut self.device {
+            Some(device) => device,
+            None => return,
+        };
+        let active = chip8.is_sound_active();
+        if active && chip8.platform() == Platform::XoChip {
+            let rate = 4000.0 * 2f32.powf((chip8.pitch() as f32 - 64.0) / 48.0);
+            device.lock().pattern = Some((*chip8.audio_pattern(), rate));
+        }
+        if active != self.playing {
+            if active {
+                device.resume();
+            } else {
+                device.pause();
+            }
+            self.playing = active;
+        }
+    }
+}
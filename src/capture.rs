@@ -0,0 +1,356 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use chip8::Chip8;
+
+use crate::palette::Palette;
+
+// palette index of the grid lines, after the four plane values
+const GRID: u8 = 4;
+
+/// How the display looks in captures
+#[derive(Debug, Clone, Copy)]
+pub struct CaptureOptions {
+    pub palette: Palette,
+    /// Size of the 64x32 pixels, hi-res pixels are half of it
+    pub scale: usize,
+    /// Draws lines between the pixels
+    pub grid: bool,
+    /// Whether the display can switch to hi-res, which needs a scale of at
+    /// least 2 to keep all the pixels
+    pub hires: bool,
+}
+
+/// Positions of the grid lines over `len` image pixels showing `n` display
+/// pixels: lines on the right and bottom edges of each pixel, when they are
+/// large enough to spare them. Used by both the window and the captures
+pub fn grid_lines(len: usize, n: usize) -> Vec<usize> {
+    if len / n < 3 {
+        return Vec::new();
+    }
+    (1..=n).map(|i| i * len / n - 1).collect()
+}
+
+impl CaptureOptions {
+    /// Size of the images, the same for both resolutions so clips can
+    /// switch between them
+    pub fn size(&self) -> (usize, usize) {
+        let scale = if self.hires {
+            self.scale.max(2)
+        } else {
+            self.scale
+        };
+        (64 * scale, 32 * scale)
+    }
+    /// The display as palette indexes, one byte per image pixel
+    fn indexed(&self, chip8: &Chip8) -> Vec<u8> {
+        let (w, h) = (chip8.width(), chip8.height());
+        let (img_w, img_h) = self.size();
+        let (mut grid_x, mut grid_y) = (vec![false; img_w], vec![false; img_h]);
+        if self.grid {
+            grid_lines(img_w, w)
+                .into_iter()
+                .for_each(|x| grid_x[x] = true);
+            grid_lines(img_h, h)
+                .into_iter()
+                .for_each(|y| grid_y[y] = true);
+        }
+        let mut img = vec![0; img_w * img_h];
+        for (img_y, row) in img.chunks_mut(img_w).enumerate() {
+            let y = img_y * h / img_h;
+            for (img_x, p) in row.iter_mut().enumerate() {
+                *p = if grid_y[img_y] || grid_x[img_x] {
+                    GRID
+                } else {
+                    chip8.gfx[y * w + img_x * w / img_w] & 0x3
+                };
+            }
+        }
+        img
+    }
+    /// RGB colors of the palette indexes
+    fn colors(&self) -> Vec<u8> {
+        let mut colors = Vec::with_capacity(15);
+        for c in self
+            .palette
+            .colors
+            .iter()
+            .chain([self.palette.grid()].iter())
+        {
+            colors.extend_from_slice(&[c.0, c.1, c.2]);
+        }
+        colors
+    }
+}
+
+/// Writes the display to a PNG file
+pub fn screenshot(chip8: &Chip8, opts: &CaptureOptions, path: &Path) -> io::Result<()> {
+    let (w, h) = opts.size();
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), w as u32, h as u32);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_palette(opts.colors());
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    writer
+        .write_image_data(&opts.indexed(chip8))
+        .map_err(io::Error::other)?;
+    writer.finish().map_err(io::Error::other)
+}
+
+enum ClipWriter {
+    Gif(gif::Encoder<BufWriter<File>>),
+    /// RGB24 frames one after the other, for ffmpeg's rawvideo
+    Raw(BufWriter<File>),
+}
+
+/// Records the display on each frame, to an animated GIF if the path ends
+/// with `.gif` or else to raw RGB24 frames
+pub struct Clip {
+    writer: ClipWriter,
+    path: PathBuf,
+    opts: CaptureOptions,
+    frames: usize,
+    /// The GIF frame not written yet as it may last longer, and the frame
+    /// it started on
+    pending: Option<(Vec<u8>, usize)>,
+}
+
+/// Hundredths of a second from the start of the clip to the start of a
+/// frame, the unit of GIF delays
+fn centis(frame: usize) -> usize {
+    frame * 100 / 60
+}
+
+impl Clip {
+    pub fn create(path: &Path, opts: CaptureOptions) -> io::Result<Clip> {
+        let file = BufWriter::new(File::create(path)?);
+        let writer = if path.extension().is_some_and(|ext| ext == "gif") {
+            let (w, h) = opts.size();
+            if w > u16::MAX as usize {
+                return Err(io::Error::other("the scale is too large for a GIF"));
+            }
+            let mut encoder = gif::Encoder::new(file, w as u16, h as u16, &opts.colors())
+                .map_err(io::Error::other)?;
+            encoder
+                .set_repeat(gif::Repeat::Infinite)
+                .map_err(io::Error::other)?;
+            ClipWriter::Gif(encoder)
+        } else {
+            ClipWriter::Raw(file)
+        };
+        Ok(Clip {
+            writer,
+            path: path.to_path_buf(),
+            opts,
+            frames: 0,
+            pending: None,
+        })
+    }
+    /// Adds the display of the next frame
+    pub fn push(&mut self, chip8: &Chip8) -> io::Result<()> {
+        let img = self.opts.indexed(chip8);
+        match &mut self.writer {
+            ClipWriter::Raw(file) => {
+                let colors = self.opts.colors();
+                let mut rgb = Vec::with_capacity(img.len() * 3);
+                for p in img {
+                    let i = p as usize * 3;
+                    rgb.extend_from_slice(&colors[i..i + 3]);
+                }
+                file.write_all(&rgb)?;
+            }
+            ClipWriter::Gif(_) => match &self.pending {
+                // an unchanged display makes the pending frame last longer
+                Some((pending, _)) if *pending == img => {}
+                // players slow down frames shorter than 2 hundredths, such
+                // frames are dropped
+                Some((_, start)) if centis(self.frames) - centis(*start) < 2 => {
+                    self.pending = Some((img, *start));
+                }
+                _ => {
+                    self.write_pending()?;
+                    self.pending = Some((img, self.frames));
+                }
+            },
+        }
+        self.frames += 1;
+        Ok(())
+    }
+    fn write_pending(&mut self) -> io::Result<()> {
+        if let (ClipWriter::Gif(encoder), Some((img, start))) = (&mut self.writer, &self.pending) {
+            let (w, h) = self.opts.size();
+            let frame = gif::Frame {
+                width: w as u16,
+                height: h as u16,
+                delay: (centis(self.frames) - centis(*start)).min(u16::MAX as usize) as u16,
+                buffer: img.into(),
+                ..gif::Frame::default()
+            };
+            encoder.write_frame(&frame).map_err(io::Error::other)?;
+        }
+        Ok(())
+    }
+    /// Number of frames recorded so far
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    /// Writes what is left and closes the file
+    pub fn finish(mut self) -> io::Result<()> {
+        self.write_pending()?;
+        match self.writer {
+            ClipWriter::Gif(encoder) => encoder.into_inner()?.flush(),
+            ClipWriter::Raw(mut file) => file.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn opts(scale: usize, grid: bool) -> CaptureOptions {
+        CaptureOptions {
+            palette: Palette::default(),
+            scale,
+            grid,
+            hires: false,
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("chip8-rs-{}-{}", std::process::id(), name))
+    }
+
+    /// Rows of the image as strings, with the palette index of each pixel
+    fn rows(img: &[u8], w: usize) -> Vec<String> {
+        img.chunks(w)
+            .map(|row| row.iter().map(|p| p.to_string()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn indexed() {
+        let c = Chip8::new().with_pixel(0, 0, 1).with_pixel(1, 1, 3);
+        let img = opts(2, false).indexed(&c);
+        assert_eq!(img.len(), 128 * 64);
+        assert_eq!(
+            rows(&img[..128 * 4], 128)
+                .iter()
+                .map(|row| &row[..6])
+                .collect::<Vec<_>>(),
+            ["110000", "110000", "003300", "003300"]
+        );
+        // the lines take the right and bottom edges of the pixels
+        let img = opts(3, true).indexed(&c);
+        assert_eq!(
+            rows(&img[..192 * 6], 192)
+                .iter()
+                .map(|row| &row[..7])
+                .collect::<Vec<_>>(),
+            ["1140040", "1140040", "4444444", "0043340", "0043340", "4444444"]
+        );
+
+        // hi-res pixels are half the size, on images of the same size
+        let c = Chip8::new()
+            .with_hires(true)
+            .with_pixel(0, 0, 2)
+            .with_pixel(127, 63, 1);
+        let img = opts(2, false).indexed(&c);
+        assert_eq!(img.len(), 128 * 64);
+        assert_eq!(&img[..3], [2, 0, 0]);
+        assert_eq!(img[128 * 64 - 1], 1);
+        // with too little room for the lines
+        assert_eq!(opts(2, true).indexed(&c), img);
+        let img = opts(6, true).indexed(&c);
+        assert_eq!(&img[..4], [2, 2, 4, 0]);
+        assert_eq!(&img[384..388], [2, 2, 4, 0]);
+        assert_eq!(&img[768..772], [4, 4, 4, 4]);
+        // scale 1 is too small for them on platforms with hi-res
+        let opts = CaptureOptions {
+            hires: true,
+            ..opts(1, false)
+        };
+        assert_eq!(opts.size(), (128, 64));
+        let img = opts.indexed(&c);
+        assert_eq!(&img[..3], [2, 0, 0]);
+        assert_eq!(&img[128 * 64 - 2..], [0, 1]);
+    }
+
+    #[test]
+    fn screenshot_png() {
+        let c = Chip8::new().with_pixel(2, 0, 1).with_pixel(63, 31, 2);
+        let path = temp_path("screenshot.png");
+        screenshot(&c, &opts(1, false), &path).unwrap();
+        let decoder = png::Decoder::new(File::open(&path).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut img = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut img).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!((info.width, info.height), (64, 32));
+        assert_eq!(info.color_type, png::ColorType::Indexed);
+        let mut expected = vec![0; 64 * 32];
+        expected[2] = 1;
+        expected[64 * 32 - 1] = 2;
+        assert_eq!(img, expected);
+        let palette = reader.info().palette.as_ref().unwrap();
+        assert_eq!(palette[..], opts(1, false).colors()[..]);
+        assert_eq!(&palette[3..6], [255, 255, 255]);
+    }
+
+    #[test]
+    fn gif_frames() {
+        let frames = [
+            Chip8::new(),
+            Chip8::new(),
+            Chip8::new().with_pixel(0, 0, 1),
+            // shorter than 2 hundredths, replaced by the next one
+            Chip8::new().with_pixel(1, 0, 1),
+            Chip8::new().with_pixel(2, 0, 1),
+            Chip8::new().with_pixel(3, 0, 1),
+            Chip8::new().with_pixel(3, 0, 1),
+        ];
+        let path = temp_path("clip.gif");
+        let mut clip = Clip::create(&path, opts(1, false)).unwrap();
+        for c in &frames {
+            clip.push(c).unwrap();
+        }
+        assert_eq!(clip.frames(), 7);
+        clip.finish().unwrap();
+
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = options.read_info(File::open(&path).unwrap()).unwrap();
+        let mut decoded = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            let lit = frame.buffer.iter().position(|p| *p == 1);
+            decoded.push((frame.delay, lit));
+        }
+        fs::remove_file(&path).unwrap();
+        // the frames start at 0, 3, 5 and 8 hundredths of a second, and the
+        // clip ends at 11
+        assert_eq!(
+            decoded,
+            [(3, None), (2, Some(0)), (3, Some(2)), (3, Some(3))]
+        );
+    }
+
+    #[test]
+    fn raw_frames() {
+        let path = temp_path("clip.rgb");
+        let mut clip = Clip::create(&path, opts(1, false)).unwrap();
+        clip.push(&Chip8::new().with_pixel(1, 0, 1)).unwrap();
+        clip.push(&Chip8::new().with_pixel(0, 0, 3)).unwrap();
+        clip.finish().unwrap();
+        let rgb = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(rgb.len(), 2 * 64 * 32 * 3);
+        assert_eq!(&rgb[..6], [0, 0, 0, 255, 255, 255]);
+        let second = &rgb[64 * 32 * 3..];
+        assert_eq!(&second[..6], [85, 85, 85, 0, 0, 0]);
+    }
+}
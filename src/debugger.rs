@@ -0,0 +1,383 @@
+use std::collections::BTreeSet;
+use std::io::{self, BufRead};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use chip8::disasm;
+use chip8::{Chip8, CpuError, StepOutcome};
+
+const HELP: &str = "\
+commands:
+  c, continue        resume the execution
+  p, pause           pause the execution
+  s, step [N]        execute N instructions, 1 by default
+  b, break [ADDR]    set a breakpoint at ADDR, or list the breakpoints
+  d, delete ADDR     remove the breakpoint at ADDR
+  r, regs            show the registers, stack and timers
+  l, list [ADDR]     disassemble around ADDR, the PC by default
+  m, mem ADDR [LEN]  dump LEN bytes of memory from ADDR, 16 by default
+  q, quit            exit the emulator
+addresses are in hex, hotkeys: F8 pause/resume, F10 step";
+
+/// Pauses, single-steps and breaks the execution of a rom. It is driven by
+/// hotkeys in the window and by commands typed on stdin.
+pub struct Debugger {
+    paused: bool,
+    /// Instructions left to execute while paused
+    steps: usize,
+    /// Skips the breakpoint check on the next instruction, so the execution
+    /// can go on from a breakpoint
+    resuming: bool,
+    breakpoints: BTreeSet<u16>,
+    commands: Receiver<String>,
+}
+
+impl Debugger {
+    /// Starts reading commands from stdin. The execution starts paused so
+    /// breakpoints can be set before the rom runs.
+    #[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+    pub fn new(chip8: &Chip8) -> Debugger {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines().map_while(Result::ok) {
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        println!("{}", HELP);
+        println!("paused at 0x{:03X}", chip8.pc());
+        Debugger {
+            paused: true,
+            steps: 0,
+            resuming: true,
+            breakpoints: BTreeSet::new(),
+            commands: rx,
+        }
+    }
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+    pub fn toggle_pause(&mut self, chip8: &Chip8) {
+        if self.paused {
+            self.resume();
+        } else {
+            self.pause(chip8);
+        }
+    }
+    fn resume(&mut self) {
+        self.paused = false;
+        self.resuming = true;
+    }
+    fn pause(&mut self, chip8: &Chip8) {
+        self.paused = true;
+        self.steps = 0;
+        println!("paused at 0x{:03X}", chip8.pc());
+    }
+    /// Executes one instruction, pausing first if running
+    pub fn step(&mut self, chip8: &Chip8) {
+        if !self.paused {
+            self.pause(chip8);
+        }
+        self.steps += 1;
+    }
+    /// Runs a frame like `Chip8::run_frame`, unless paused, in which case
+    /// only the requested steps are executed, at most `ipf` of them with the
+    /// rest left to the next frames, and the timers don't move.
+    /// Stops the frame early on a breakpoint, and pauses on an error of the
+    /// rom instead of returning it so the machine can be inspected.
+    pub fn run_frame(&mut self, chip8: &mut Chip8, ipf: usize) -> StepOutcome {
+        self.read_commands(chip8);
+        if self.paused {
+            let mut outcome = StepOutcome::Executed;
+            for _ in 0..self.steps.min(ipf) {
+                self.steps -= 1;
+                outcome = match chip8.step() {
+                    Ok(outcome) => outcome,
+                    Err(err) => {
+                        self.fault(chip8, err);
+                        break;
+                    }
+                };
+                println!("{}", instruction_line(chip8, chip8.pc()));
+                if outcome == StepOutcome::Exit {
+                    break;
+                }
+            }
+            return outcome;
+        }
+        let mut outcome = StepOutcome::Executed;
+        for _ in 0..ipf {
+            if !self.resuming && self.breakpoints.contains(&chip8.pc()) {
+                println!("breakpoint at 0x{:03X}", chip8.pc());
+                self.pause(chip8);
+                break;
+            }
+            self.resuming = false;
+            outcome = match chip8.step() {
+                Ok(outcome) => outcome,
+                Err(err) => {
+                    self.fault(chip8, err);
+                    return StepOutcome::Executed;
+                }
+            };
+            if outcome != StepOutcome::Executed {
+                break;
+            }
+        }
+        chip8.tick_timers();
+        outcome
+    }
+    /// Pauses on the instruction that failed, which is left unexecuted
+    fn fault(&mut self, chip8: &Chip8, err: CpuError) {
+        println!("error: {}", err);
+        self.pause(chip8);
+        print!("{}", registers(chip8));
+    }
+    fn read_commands(&mut self, chip8: &Chip8) {
+        while let Ok(line) = self.commands.try_recv() {
+            if let Err(err) = self.command(chip8, &line) {
+                println!("{}", err);
+            }
+        }
+    }
+    fn command(&mut self, chip8: &Chip8, line: &str) -> Result<(), String> {
+        let mut args = line.split_whitespace();
+        let cmd = match args.next() {
+            Some(cmd) => cmd,
+            None => return Ok(()),
+        };
+        let args: Vec<&str> = args.collect();
+        let arg = |i: usize| -> Result<Option<u16>, String> {
+            match args.get(i) {
+                Some(arg) => parse_hex(arg).map(Some),
+                None => Ok(None),
+            }
+        };
+        match cmd {
+            "h" | "help" => println!("{}", HELP),
+            "c" | "continue" => self.resume(),
+            "p" | "pause" => self.pause(chip8),
+            "s" | "step" => {
+                let n = match args.first() {
+                    Some(n) => n.parse().map_err(|_| format!("invalid count '{}'", n))?,
+                    None => 1,
+                };
+                if !self.paused {
+                    self.pause(chip8);
+                }
+                self.steps += n;
+            }
+            "b" | "break" => match arg(0)? {
+                Some(addr) => {
+                    self.breakpoints.insert(addr);
+                }
+                None => {
+                    for addr in &self.breakpoints {
+                        println!("0x{:03X}", addr);
+                    }
+                }
+            },
+            "d" | "delete" => {
+                let addr = arg(0)?.ok_or("missing address")?;
+                if !self.breakpoints.remove(&addr) {
+                    return Err(format!("no breakpoint at 0x{:03X}", addr));
+                }
+            }
+            "r" | "regs" => print!("{}", registers(chip8)),
+            "l" | "list" => {
+                let pc = chip8.pc();
+                let start = arg(0)?.unwrap_or(pc).saturating_sub(8);
+                for i in 0..10 {
+                    let addr = start.wrapping_add(i * 2);
+                    let marker = if addr == pc {
+                        '>'
+                    } else if self.breakpoints.contains(&addr) {
+                        '*'
+                    } else {
+                        ' '
+                    };
+                    println!("{} {}", marker, instruction_line(chip8, addr));
+                }
+            }
+            "m" | "mem" => {
+                let addr = arg(0)?.ok_or("missing address")? as usize;
+                let len = match args.get(1) {
+                    Some(len) => len
+                        .parse()
+                        .map_err(|_| format!("invalid length '{}'", len))?,
+                    None => 16,
+                };
+                for line in dump(chip8.memory(), addr, len) {
+                    println!("{}", line);
+                }
+            }
+            "q" | "quit" => std::process::exit(0),
+            _ => return Err(format!("unknown command '{}', type help", cmd)),
+        }
+        Ok(())
+    }
+}
+
+fn parse_hex(s: &str) -> Result<u16, String> {
+    let digits = s.trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(digits, 16).map_err(|_| format!("invalid address '{}'", s))
+}
+
+/// Rows of 16 of the `len` bytes of memory from `addr`, cut at the end of
+/// the memory
+fn dump(memory: &[u8], addr: usize, len: usize) -> Vec<String> {
+    let end = addr.saturating_add(len).min(memory.len());
+    memory[addr.min(end)..end]
+        .chunks(16)
+        .enumerate()
+        .map(|(i, row)| {
+            let hex: Vec<String> = row.iter().map(|b| format!("{:02X}", b)).collect();
+            format!("0x{:03X}  {}", addr + i * 16, hex.join(" "))
+        })
+        .collect()
+}
+
+/// Address, bytes and mnemonic of the instruction at `addr`
+fn instruction_line(chip8: &Chip8, addr: u16) -> String {
+    let memory = chip8.memory();
+    match disasm::decode_at(memory, addr as usize) {
+        Some((instruction, size)) => {
+            let bytes = &memory[addr as usize..addr as usize + size];
+            let hex: String = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            format!("0x{:03X}  {:<8}  {}", addr, hex, instruction)
+        }
+        None => format!("0x{:03X}  out of memory", addr),
+    }
+}
+
+fn registers(chip8: &Chip8) -> String {
+    let v: Vec<String> = chip8
+        .v()
+        .iter()
+        .enumerate()
+        .map(|(i, v)| format!("V{:X}={:02X}", i, v))
+        .collect();
+    let stack: Vec<String> = chip8.stack()[..chip8.sp()]
+        .iter()
+        .map(|a| format!("0x{:03X}", a))
+        .collect();
+    format!(
+        "PC=0x{:03X} I=0x{:03X} opcode={:04X} DT={} ST={}\n{}\nstack: [{}]\n",
+        chip8.pc(),
+        chip8.index(),
+        chip8.opcode(),
+        chip8.delay_timer(),
+        chip8.sound_timer(),
+        v.join(" "),
+        stack.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8::{Platform, Quirks};
+
+    /// A paused debugger without the stdin reader
+    fn debugger() -> Debugger {
+        Debugger {
+            paused: true,
+            steps: 0,
+            resuming: true,
+            breakpoints: BTreeSet::new(),
+            commands: mpsc::channel().1,
+        }
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!(parse_hex("0x2A0"), Ok(0x2A0));
+        assert_eq!(parse_hex("0XFFF"), Ok(0xFFF));
+        assert_eq!(parse_hex("ffff"), Ok(0xFFFF));
+        assert_eq!(parse_hex("10000"), Err("invalid address '10000'".into()));
+        assert_eq!(parse_hex("0x"), Err("invalid address '0x'".into()));
+        assert_eq!(parse_hex("20g"), Err("invalid address '20g'".into()));
+    }
+
+    #[test]
+    fn commands() {
+        let (mut d, c) = (debugger(), Chip8::new());
+        assert_eq!(d.command(&c, "  "), Ok(()));
+        assert_eq!(d.command(&c, "s"), Ok(()));
+        assert_eq!(d.command(&c, "step 3"), Ok(()));
+        assert_eq!(d.steps, 4);
+        assert_eq!(d.command(&c, "s -1"), Err("invalid count '-1'".into()));
+        assert_eq!(d.command(&c, "c"), Ok(()));
+        assert!(!d.is_paused());
+        // stepping pauses first, which drops the steps left
+        d.step(&c);
+        assert!(d.is_paused());
+        assert_eq!(d.steps, 1);
+
+        assert_eq!(d.command(&c, "b 0x20A"), Ok(()));
+        assert_eq!(d.command(&c, "break 200"), Ok(()));
+        assert_eq!(d.command(&c, "b 2x0"), Err("invalid address '2x0'".into()));
+        assert_eq!(d.breakpoints.iter().collect::<Vec<_>>(), [&0x200, &0x20A]);
+        assert_eq!(d.command(&c, "d 20a"), Ok(()));
+        assert_eq!(
+            d.command(&c, "delete 20A"),
+            Err("no breakpoint at 0x20A".into())
+        );
+        assert_eq!(d.command(&c, "d"), Err("missing address".into()));
+
+        assert_eq!(d.command(&c, "m"), Err("missing address".into()));
+        assert_eq!(d.command(&c, "m 200 x"), Err("invalid length 'x'".into()));
+        assert_eq!(
+            d.command(&c, "x 200"),
+            Err("unknown command 'x', type help".into())
+        );
+    }
+
+    #[test]
+    fn memory_dump() {
+        let mut memory = vec![0; 0x10000];
+        memory[0xFFFF] = 0xAB;
+        assert_eq!(
+            dump(&memory, 0x200, 20),
+            [
+                "0x200  00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00",
+                "0x210  00 00 00 00"
+            ]
+        );
+        // cut at the end of the memory, without overflowing
+        assert_eq!(dump(&memory, 0xFFFC, 16), ["0xFFFC  00 00 00 AB"]);
+        assert_eq!(dump(&memory, 0xFFFF, usize::MAX), ["0xFFFF  AB"]);
+        assert!(dump(&memory[..0x1000], 0xFFFF, 16).is_empty());
+        assert!(dump(&memory, 0x200, 0).is_empty());
+    }
+
+    #[test]
+    fn breakpoints_and_steps() {
+        // 7001 1200: count in V0 forever
+        let mut c = Chip8::with_platform(Platform::Chip8, Quirks::vip());
+        c.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        let mut d = debugger();
+        // the steps are spread over the frames
+        d.command(&c, "s 25").unwrap();
+        d.run_frame(&mut c, 10);
+        assert_eq!((c.v()[0], d.steps), (5, 15));
+        d.run_frame(&mut c, 10);
+        d.run_frame(&mut c, 10);
+        assert_eq!((c.v()[0], d.steps), (13, 0));
+        d.run_frame(&mut c, 10);
+        assert_eq!(c.v()[0], 13);
+
+        // running goes on from the breakpoint it is paused at, and stops
+        // before the instruction at the next one
+        assert_eq!(c.pc(), 0x202);
+        d.command(&c, "b 202").unwrap();
+        d.toggle_pause(&c);
+        d.run_frame(&mut c, 10);
+        assert!(d.is_paused());
+        assert_eq!((c.pc(), c.v()[0]), (0x202, 14));
+        d.toggle_pause(&c);
+        d.run_frame(&mut c, 10);
+        assert_eq!((c.pc(), c.v()[0]), (0x202, 15));
+    }
+}
@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+
+use sdl2::controller::GameController;
+use sdl2::event::Event;
+use sdl2::GameControllerSubsystem;
+
+use crate::keymap::Keymap;
+
+/// The game controllers plugged in, opened and closed as they come and go
+pub struct Gamepads {
+    subsystem: Option<GameControllerSubsystem>,
+    /// Open controllers by joystick instance id
+    controllers: HashMap<u32, GameController>,
+}
+
+impl Gamepads {
+    /// Opens the controllers already plugged in. Without a controller
+    /// subsystem the keyboard still works, and no controller is ever opened
+    pub fn new(sdl_context: &sdl2::Sdl) -> Gamepads {
+        let mut gamepads = Gamepads {
+            subsystem: None,
+            controllers: HashMap::new(),
+        };
+        match sdl_context.game_controller() {
+            Ok(subsystem) => {
+                let count = subsystem.num_joysticks().unwrap_or(0);
+                gamepads.subsystem = Some(subsystem);
+                for index in 0..count {
+                    gamepads.open(index);
+                }
+            }
+            Err(err) => eprintln!("Gamepads disabled: {}", err),
+        }
+        gamepads
+    }
+    fn open(&mut self, index: u32) {
+        let subsystem = match &self.subsystem {
+            Some(subsystem) if subsystem.is_game_controller(index) => subsystem,
+            _ => return,
+        };
+        match subsystem.open(index) {
+            Ok(controller) => {
+                let id = controller.instance_id();
+                if !self.controllers.contains_key(&id) {
+                    println!("Gamepad connected: {}", controller.name());
+                }
+                self.controllers.insert(id, controller);
+            }
+            Err(err) => eprintln!("Error opening gamepad {}: {}", index, err),
+        }
+    }
+    /// Opens and closes the controllers plugged in and out. SDL also sends
+    /// an added event for those plugged in at start, which are already open
+    pub fn handle_event(&mut self, event: &Event) {
+        match event {
+            Event::ControllerDeviceAdded { which, .. } => self.open(*which),
+            Event::ControllerDeviceRemoved { which, .. } => {
+                if let Some(controller) = self.controllers.remove(which) {
+                    println!("Gamepad disconnected: {}", controller.name());
+                }
+            }
+            _ => {}
+        }
+    }
+    /// Sets the CHIP-8 keys held on any controller
+    pub fn held_keys(&self, keymap: &Keymap, keys: &mut [u8; 16]) {
+        for controller in self.controllers.values() {
+            keymap.controller_keys(controller, keys);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sdl2::controller::{Axis, Button};
+    use sdl2::sys;
+
+    // the virtual joysticks of SDL 2.0.14, which the bindings don't have yet
+    extern "C" {
+        fn SDL_JoystickAttachVirtual(
+            kind: sys::SDL_JoystickType,
+            naxes: i32,
+            nbuttons: i32,
+            nhats: i32,
+        ) -> i32;
+        fn SDL_JoystickSetVirtualAxis(
+            joystick: *mut sys::SDL_Joystick,
+            axis: i32,
+            value: i16,
+        ) -> i32;
+        fn SDL_JoystickSetVirtualButton(
+            joystick: *mut sys::SDL_Joystick,
+            button: i32,
+            value: u8,
+        ) -> i32;
+    }
+
+    /// A virtual controller whose buttons and axes are numbered like the
+    /// ones of `Button` and `Axis`. The triggers only use the positive half
+    /// of their axis, so they read the value they are set to
+    struct Virtual {
+        joystick: *mut sys::SDL_Joystick,
+        subsystem: GameControllerSubsystem,
+    }
+
+    impl Virtual {
+        fn button(&self, button: Button, pressed: bool) {
+            let result = unsafe {
+                SDL_JoystickSetVirtualButton(self.joystick, button as i32, pressed as u8)
+            };
+            assert_eq!(result, 0, "{}", sdl2::get_error());
+        }
+        fn axis(&self, axis: Axis, value: i16) {
+            let result = unsafe { SDL_JoystickSetVirtualAxis(self.joystick, axis as i32, value) };
+            assert_eq!(result, 0, "{}", sdl2::get_error());
+        }
+        /// Keys held after applying the changes to the controller
+        fn held(&self, gamepads: &Gamepads, keymap: &Keymap) -> Vec<u8> {
+            self.subsystem.update();
+            let mut keys = [0; 16];
+            gamepads.held_keys(keymap, &mut keys);
+            (0..16).filter(|&k| keys[k as usize] == 1).collect()
+        }
+    }
+
+    // a single test, as SDL can only be initialized once at a time
+    #[test]
+    fn virtual_controller() {
+        let sdl = sdl2::init().unwrap();
+        let subsystem = sdl.game_controller().unwrap();
+        let index = unsafe {
+            SDL_JoystickAttachVirtual(
+                sys::SDL_JoystickType::SDL_JOYSTICK_TYPE_GAMECONTROLLER,
+                6,
+                15,
+                0,
+            )
+        };
+        assert!(index >= 0, "{}", sdl2::get_error());
+        let guid = sdl.joystick().unwrap().device_guid(index as u32).unwrap();
+        subsystem
+            .add_mapping(&format!(
+                "{},Virtual,a:b0,b:b1,x:b2,y:b3,back:b4,guide:b5,start:b6,leftstick:b7,\
+                 rightstick:b8,leftshoulder:b9,rightshoulder:b10,dpup:b11,dpdown:b12,\
+                 dpleft:b13,dpright:b14,leftx:a0,lefty:a1,rightx:a2,righty:a3,\
+                 lefttrigger:+a4,righttrigger:+a5",
+                guid
+            ))
+            .unwrap();
+
+        let gamepads = Gamepads::new(&sdl);
+        assert_eq!(gamepads.controllers.len(), 1);
+        let id = *gamepads.controllers.keys().next().unwrap();
+        let pad = Virtual {
+            joystick: unsafe { sys::SDL_JoystickFromInstanceID(id as i32) },
+            subsystem,
+        };
+        assert!(!pad.joystick.is_null());
+
+        // the default bindings
+        let keymap = Keymap::default();
+        assert_eq!(pad.held(&gamepads, &keymap), []);
+        for (button, key) in [
+            (Button::DPadUp, 0x5),
+            (Button::DPadLeft, 0x7),
+            (Button::DPadDown, 0x8),
+            (Button::DPadRight, 0x9),
+            (Button::A, 0x6),
+            (Button::B, 0x4),
+            (Button::X, 0x1),
+            (Button::Y, 0x2),
+            (Button::Back, 0x0),
+            (Button::Start, 0xF),
+        ] {
+            pad.button(button, true);
+            assert_eq!(pad.held(&gamepads, &keymap), [key], "{:?}", button);
+            pad.button(button, false);
+        }
+        pad.button(Button::Guide, true);
+        pad.button(Button::LeftShoulder, true);
+        assert_eq!(pad.held(&gamepads, &keymap), []);
+        pad.button(Button::Guide, false);
+        pad.button(Button::LeftShoulder, false);
+        pad.button(Button::DPadUp, true);
+        pad.button(Button::A, true);
+        assert_eq!(pad.held(&gamepads, &keymap), [0x5, 0x6]);
+        pad.button(Button::DPadUp, false);
+        pad.button(Button::A, false);
+
+        // the left stick, past a dead zone of a quarter of the range
+        for (axis, value, held) in [
+            (Axis::LeftX, 8000, vec![]),
+            (Axis::LeftX, -8000, vec![]),
+            (Axis::LeftX, 9000, vec![0x9]),
+            (Axis::LeftX, -9000, vec![0x7]),
+            (Axis::LeftY, i16::MAX, vec![0x8]),
+            (Axis::LeftY, i16::MIN, vec![0x5]),
+            (Axis::RightX, i16::MAX, vec![]),
+            (Axis::TriggerRight, i16::MAX, vec![]),
+        ] {
+            pad.axis(axis, value);
+            assert_eq!(pad.held(&gamepads, &keymap), held, "{:?} {}", axis, value);
+            pad.axis(axis, 0);
+        }
+
+        // per-rom bindings on top of the global ones
+        let config = r#"
+            [gamepad]
+            deadzone = 0.5
+            6 = ["a", "righttrigger+"]
+
+            [roms."pong.c8"]
+            gamepad = { 1 = "dpup", 4 = ["dpdown", "lefty+"] }
+        "#;
+        let keymap = Keymap::from_config(config, "other.c8").unwrap();
+        for (button, axis, value, held) in [
+            (Button::DPadUp, Axis::LeftY, 12000, vec![0x5]),
+            (Button::DPadDown, Axis::LeftY, 20000, vec![0x8]),
+            (Button::A, Axis::TriggerRight, 20000, vec![0x6]),
+            (Button::B, Axis::TriggerRight, 12000, vec![0x4]),
+        ] {
+            pad.button(button, true);
+            pad.axis(axis, value);
+            assert_eq!(
+                pad.held(&gamepads, &keymap),
+                held,
+                "{:?} {:?}",
+                button,
+                axis
+            );
+            pad.button(button, false);
+            pad.axis(axis, 0);
+        }
+        pad.axis(Axis::TriggerRight, 20000);
+        assert_eq!(pad.held(&gamepads, &keymap), [0x6]);
+        pad.axis(Axis::TriggerRight, 0);
+
+        let keymap = Keymap::from_config(config, "pong.c8").unwrap();
+        for (button, held) in [
+            (Button::DPadUp, 0x1),
+            (Button::DPadDown, 0x4),
+            (Button::DPadLeft, 0x7),
+            (Button::A, 0x6),
+        ] {
+            pad.button(button, true);
+            assert_eq!(pad.held(&gamepads, &keymap), [held], "{:?}", button);
+            pad.button(button, false);
+        }
+        pad.axis(Axis::LeftY, 20000);
+        assert_eq!(pad.held(&gamepads, &keymap), [0x4]);
+        pad.axis(Axis::LeftY, -20000);
+        assert_eq!(pad.held(&gamepads, &keymap), [0x5]);
+    }
+}
@@ -0,0 +1,147 @@
+use std::fmt::Write;
+use std::io;
+
+use chip8::{Chip8, CpuError, StepOutcome};
+
+use crate::capture::Clip;
+use crate::input::{InputSource, Recorder};
+
+/// How long to run a rom without a display
+#[derive(Debug, Clone, Copy)]
+pub enum Limit {
+    /// Number of instructions
+    Cycles(u64),
+    /// Number of 60 Hz frames
+    Frames(u64),
+}
+
+pub struct Summary {
+    pub cycles: u64,
+    pub frames: u64,
+    /// Outcome of the last instruction executed
+    pub outcome: Result<StepOutcome, CpuError>,
+    /// Error that stopped the capture of the display, after which the rom
+    /// kept running
+    pub clip_error: Option<io::Error>,
+}
+
+/// Runs the rom executing `ipf` instructions per frame and ticking the timers
+/// once per frame, the same as the SDL frontend but without waiting between
+/// frames. Stops at the limit, or before if the rom exits or fails. The keys
+/// come from `input`, with none held once it is over, and are recorded by
+/// `recorder`. The display of each frame is recorded by `clip`, until it
+/// fails.
+pub fn run(
+    chip8: &mut Chip8,
+    ipf: usize,
+    limit: Limit,
+    input: &mut dyn InputSource,
+    mut recorder: Option<&mut Recorder>,
+    mut clip: Option<&mut Clip>,
+) -> Summary {
+    let mut summary = Summary {
+        cycles: 0,
+        frames: 0,
+        outcome: Ok(StepOutcome::Executed),
+        clip_error: None,
+    };
+    'frames: loop {
+        match limit {
+            Limit::Cycles(n) if summary.cycles >= n => break,
+            Limit::Frames(n) if summary.frames >= n => break,
+            _ => {}
+        }
+        chip8.key = input.next_frame(&[0; 16]).unwrap_or([0; 16]);
+        if let Some(recorder) = recorder.as_mut() {
+            recorder.push(&chip8.key);
+        }
+        for _ in 0..ipf {
+            if let Limit::Cycles(n) = limit {
+                if summary.cycles >= n {
+                    break;
+                }
+            }
+            summary.outcome = chip8.step();
+            match summary.outcome {
+                Ok(StepOutcome::Executed) => summary.cycles += 1,
+                // nobody is going to press a key, move on to the next frame
+                Ok(StepOutcome::WaitingForKey) => {
+                    summary.cycles += 1;
+                    break;
+                }
+                _ => break 'frames,
+            }
+        }
+        chip8.tick_timers();
+        summary.frames += 1;
+        if let Some(c) = clip.as_mut() {
+            if let Err(err) = c.push(chip8) {
+                summary.clip_error = Some(err);
+                clip = None;
+            }
+        }
+    }
+    summary
+}
+
+/// Draws the display with one character per pixel, '.' for unset pixels,
+/// '#' for the first plane, '+' for the second plane and '*' for both
+pub fn ascii_screen(chip8: &Chip8) -> String {
+    const CHARS: [char; 4] = ['.', '#', '+', '*'];
+    let mut s = String::new();
+    for row in chip8.gfx.chunks(chip8.width()) {
+        for pixel in row {
+            s.push(CHARS[*pixel as usize & 0x3]);
+        }
+        s.push('\n');
+    }
+    s
+}
+
+fn json_list<T: ToString>(items: &[T]) -> String {
+    let items: Vec<String> = items.iter().map(|i| i.to_string()).collect();
+    format!("[{}]", items.join(", "))
+}
+
+fn json_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Registers, timers and run summary as a JSON object
+pub fn state_json(chip8: &Chip8, summary: &Summary) -> String {
+    let (outcome, error) = match &summary.outcome {
+        Ok(StepOutcome::Executed) => ("executed", "null".to_string()),
+        Ok(StepOutcome::WaitingForKey) => ("waiting_for_key", "null".to_string()),
+        Ok(StepOutcome::Exit) => ("exit", "null".to_string()),
+        Err(err) => ("error", json_string(&err.to_string())),
+    };
+    let mut s = String::new();
+    writeln!(s, "{{").unwrap();
+    writeln!(
+        s,
+        "  \"platform\": {},",
+        json_string(&chip8.platform().to_string())
+    )
+    .unwrap();
+    writeln!(s, "  \"seed\": {},", chip8.seed()).unwrap();
+    writeln!(s, "  \"cycles\": {},", summary.cycles).unwrap();
+    writeln!(s, "  \"frames\": {},", summary.frames).unwrap();
+    writeln!(s, "  \"outcome\": {},", json_string(outcome)).unwrap();
+    writeln!(s, "  \"error\": {},", error).unwrap();
+    writeln!(s, "  \"pc\": {},", chip8.pc()).unwrap();
+    writeln!(s, "  \"index\": {},", chip8.index()).unwrap();
+    writeln!(s, "  \"v\": {},", json_list(chip8.v())).unwrap();
+    writeln!(s, "  \"sp\": {},", chip8.sp()).unwrap();
+    writeln!(
+        s,
+        "  \"stack\": {},",
+        json_list(&chip8.stack()[..chip8.sp()])
+    )
+    .unwrap();
+    writeln!(s, "  \"delay_timer\": {},", chip8.delay_timer()).unwrap();
+    writeln!(s, "  \"sound_timer\": {},", chip8.sound_timer()).unwrap();
+    writeln!(s, "  \"width\": {},", chip8.width()).unwrap();
+    writeln!(s, "  \"height\": {}", chip8.height()).unwrap();
+    writeln!(s, "}}").unwrap();
+    s
+}
@@ -0,0 +1,70 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chip8::movie::Movie;
+
+/// Where the keys held on each frame come from
+pub trait InputSource {
+    /// Keys held during the next frame, given the ones held on the
+    /// keyboard. None once the input is over
+    fn next_frame(&mut self, keyboard: &[u8; 16]) -> Option<[u8; 16]>;
+}
+
+/// The keys held on the keyboard
+pub struct Live;
+
+impl InputSource for Live {
+    fn next_frame(&mut self, keyboard: &[u8; 16]) -> Option<[u8; 16]> {
+        Some(*keyboard)
+    }
+}
+
+/// The keys recorded in a movie, ignoring the keyboard
+pub struct Replay {
+    movie: Movie,
+    frame: usize,
+}
+
+impl Replay {
+    pub fn new(movie: Movie) -> Replay {
+        Replay { movie, frame: 0 }
+    }
+}
+
+impl InputSource for Replay {
+    fn next_frame(&mut self, _keyboard: &[u8; 16]) -> Option<[u8; 16]> {
+        let keys = self.movie.keys(self.frame);
+        self.frame += 1;
+        keys
+    }
+}
+
+/// Records the keys of each frame into a movie file
+pub struct Recorder {
+    movie: Movie,
+    path: PathBuf,
+}
+
+impl Recorder {
+    pub fn new(movie: Movie, path: &Path) -> Recorder {
+        Recorder {
+            movie,
+            path: path.to_path_buf(),
+        }
+    }
+    pub fn push(&mut self, keys: &[u8; 16]) {
+        self.movie.push(keys);
+    }
+    /// Writes the movie recorded so far
+    pub fn save(&self) -> io::Result<()> {
+        fs::write(&self.path, self.movie.to_bytes())
+    }
+    /// Number of frames recorded so far
+    pub fn frames(&self) -> usize {
+        self.movie.frames.len()
+    }
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
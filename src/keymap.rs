@@ -0,0 +1,491 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use sdl2::controller::{Axis, Button, GameController};
+use sdl2::keyboard::Keycode;
+use toml::Value;
+
+// CHIP-8 keys in the order of the hex keypad, row by row:
+// 1 2 3 C / 4 5 6 D / 7 8 9 E / A 0 B F
+const PAD: [u8; 16] = [
+    0x1, 0x2, 0x3, 0xC, 0x4, 0x5, 0x6, 0xD, 0x7, 0x8, 0x9, 0xE, 0xA, 0x0, 0xB, 0xF,
+];
+
+// keyboard keys laid out like the keypad on each layout, in the order of PAD
+const PRESETS: [(&str, [&str; 16]); 3] = [
+    (
+        "qwerty",
+        [
+            "1", "2", "3", "4", "Q", "W", "E", "R", "A", "S", "D", "F", "Z", "X", "C", "V",
+        ],
+    ),
+    (
+        "azerty",
+        [
+            "1", "2", "3", "4", "A", "Z", "E", "R", "Q", "S", "D", "F", "W", "X", "C", "V",
+        ],
+    ),
+    (
+        "dvorak",
+        [
+            "1", "2", "3", "4", "'", ",", ".", "P", "A", "O", "E", "U", ";", "Q", "J", "K",
+        ],
+    ),
+];
+
+// controller bindings when none are configured: the d-pad and left stick
+// on 5, 7, 8 and 9, which most roms use as arrows, and the rest of the
+// buttons on keys commonly used for actions
+const GAMEPAD: [(&str, u8); 14] = [
+    ("dpup", 0x5),
+    ("dpleft", 0x7),
+    ("dpdown", 0x8),
+    ("dpright", 0x9),
+    ("lefty-", 0x5),
+    ("leftx-", 0x7),
+    ("lefty+", 0x8),
+    ("leftx+", 0x9),
+    ("a", 0x6),
+    ("b", 0x4),
+    ("x", 0x1),
+    ("y", 0x2),
+    ("back", 0x0),
+    ("start", 0xF),
+];
+
+// stick movements smaller than this fraction of the range are ignored
+const DEADZONE: f64 = 0.25;
+
+#[derive(Debug)]
+pub enum KeymapError {
+    /// The config file could not be read
+    Io(io::Error),
+    /// The config file is not valid TOML
+    Toml(toml::de::Error),
+    UnknownPreset(String),
+    /// A CHIP-8 key that is not a hex digit
+    InvalidChip8Key(String),
+    /// A keyboard key name unknown to SDL
+    UnknownKey {
+        chip8_key: u8,
+        name: String,
+    },
+    /// A controller button or axis name unknown to SDL
+    UnknownButton {
+        chip8_key: u8,
+        name: String,
+    },
+    /// A field with an unexpected name or type
+    Invalid(String),
+}
+
+impl fmt::Display for KeymapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeymapError::Io(err) => write!(f, "can not read keymap file: {}", err),
+            KeymapError::Toml(err) => write!(f, "invalid keymap file: {}", err),
+            KeymapError::UnknownPreset(name) => write!(
+                f,
+                "unknown keymap preset '{}', expected one of: qwerty, azerty, dvorak",
+                name
+            ),
+            KeymapError::InvalidChip8Key(key) => {
+                write!(f, "invalid CHIP-8 key '{}', expected 0-9 or A-F", key)
+            }
+            KeymapError::UnknownKey { chip8_key, name } => write!(
+                f,
+                "unknown keyboard key '{}' for CHIP-8 key {:X}, \
+                 the names are listed in https://wiki.libsdl.org/SDL_Keycode",
+                name, chip8_key
+            ),
+            KeymapError::UnknownButton { chip8_key, name } => write!(
+                f,
+                "unknown controller button '{}' for CHIP-8 key {:X}, expected a button \
+                 (a, b, x, y, back, guide, start, leftstick, rightstick, leftshoulder, \
+                 rightshoulder, dpup, dpdown, dpleft, dpright) or an axis direction \
+                 (leftx-, leftx+, lefty-, lefty+, rightx-, rightx+, righty-, righty+, \
+                 lefttrigger+, righttrigger+)",
+                name, chip8_key
+            ),
+            KeymapError::Invalid(msg) => write!(f, "invalid keymap: {}", msg),
+        }
+    }
+}
+
+impl Error for KeymapError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            KeymapError::Io(err) => Some(err),
+            KeymapError::Toml(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Keyboard keys and controller buttons bound to each CHIP-8 key
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    keys: HashMap<Keycode, u8>,
+    buttons: HashMap<Button, u8>,
+    /// Axes held past the dead zone, true for the positive direction
+    axes: HashMap<(Axis, bool), u8>,
+    deadzone: i16,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap::preset("qwerty").unwrap()
+    }
+}
+
+impl Keymap {
+    /// The keypad laid out on the left of a qwerty, azerty or dvorak
+    /// keyboard, with the default controller bindings
+    pub fn preset(name: &str) -> Result<Keymap, KeymapError> {
+        let names = match PRESETS.iter().find(|(preset, _)| *preset == name) {
+            Some((_, names)) => names,
+            None => return Err(KeymapError::UnknownPreset(name.to_string())),
+        };
+        let mut keymap = Keymap {
+            keys: HashMap::new(),
+            buttons: HashMap::new(),
+            axes: HashMap::new(),
+            deadzone: (DEADZONE * i16::MAX as f64) as i16,
+        };
+        for (chip8_key, name) in PAD.iter().zip(names.iter()) {
+            keymap.bind(*chip8_key, &[name])?;
+        }
+        for (name, chip8_key) in GAMEPAD.iter() {
+            keymap.bind_button(*chip8_key, name, false)?;
+        }
+        Ok(keymap)
+    }
+    /// Keymap given on the command line: a preset name, or the path of a
+    /// TOML config file in which the section of the rom is used if present
+    pub fn from_arg(arg: &str, rom_path: &Path) -> Result<Keymap, KeymapError> {
+        if PRESETS.iter().any(|(preset, _)| *preset == arg) {
+            return Keymap::preset(arg);
+        }
+        let config = fs::read_to_string(arg).map_err(KeymapError::Io)?;
+        let rom = rom_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Keymap::from_config(&config, &rom)
+    }
+    /// Reads a config like:
+    ///
+    /// ```toml
+    /// preset = "azerty"
+    ///
+    /// [keys]
+    /// 5 = ["Z", "Up"]
+    ///
+    /// [gamepad]
+    /// deadzone = 0.4
+    /// 6 = ["a", "righttrigger+"]
+    ///
+    /// [roms."pong.c8"]
+    /// preset = "qwerty"
+    /// keys = { 1 = "Up", 4 = "Down" }
+    /// gamepad = { 1 = "dpup", 4 = "dpdown" }
+    /// ```
+    ///
+    /// `preset` is the starting keyboard layout, qwerty by default, and
+    /// `keys` binds CHIP-8 keys to one or more keyboard keys, replacing the
+    /// preset ones. `gamepad` does the same with controller buttons and
+    /// axis directions, and sets the fraction of the stick range ignored
+    /// around the center. The section of `rom` under `roms` is applied on
+    /// top.
+    pub fn from_config(config: &str, rom: &str) -> Result<Keymap, KeymapError> {
+        let config: Value = config.parse().map_err(KeymapError::Toml)?;
+        let mut keymap = Keymap::default();
+        keymap.apply(&config, &["preset", "keys", "gamepad", "roms"])?;
+        if let Some(roms) = config.get("roms") {
+            let roms = roms
+                .as_table()
+                .ok_or_else(|| KeymapError::Invalid("roms must be a table".to_string()))?;
+            if let Some(section) = roms.get(rom) {
+                keymap.apply(section, &["preset", "keys", "gamepad"])?;
+            }
+        }
+        Ok(keymap)
+    }
+    /// Applies the `preset`, `keys` and `gamepad` of a config table
+    fn apply(&mut self, table: &Value, fields: &[&str]) -> Result<(), KeymapError> {
+        let table = table
+            .as_table()
+            .ok_or_else(|| KeymapError::Invalid("expected a table".to_string()))?;
+        if let Some(field) = table.keys().find(|k| !fields.contains(&k.as_str())) {
+            return Err(KeymapError::Invalid(format!(
+                "unknown field '{}', expected one of: {}",
+                field,
+                fields.join(", ")
+            )));
+        }
+        if let Some(preset) = table.get("preset") {
+            let preset = preset
+                .as_str()
+                .ok_or_else(|| KeymapError::Invalid("preset must be a string".to_string()))?;
+            self.keys = Keymap::preset(preset)?.keys;
+        }
+        if let Some(keys) = table.get("keys") {
+            let keys = keys
+                .as_table()
+                .ok_or_else(|| KeymapError::Invalid("keys must be a table".to_string()))?;
+            for (chip8_key, names) in keys {
+                let (chip8_key, names) = bindings(chip8_key, names)?;
+                self.bind(chip8_key, &names)?;
+            }
+        }
+        if let Some(gamepad) = table.get("gamepad") {
+            let gamepad = gamepad
+                .as_table()
+                .ok_or_else(|| KeymapError::Invalid("gamepad must be a table".to_string()))?;
+            for (chip8_key, names) in gamepad {
+                if chip8_key == "deadzone" {
+                    self.deadzone = match names.as_float() {
+                        Some(d) if (0.0..1.0).contains(&d) => (d * i16::MAX as f64) as i16,
+                        _ => {
+                            return Err(KeymapError::Invalid(
+                                "deadzone must be a number from 0 to 1".to_string(),
+                            ))
+                        }
+                    };
+                    continue;
+                }
+                let (chip8_key, names) = bindings(chip8_key, names)?;
+                for (i, name) in names.iter().enumerate() {
+                    // the first binding replaces the previous ones
+                    self.bind_button(chip8_key, name, i > 0)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Binds a CHIP-8 key to the given keyboard keys only
+    fn bind(&mut self, chip8_key: u8, names: &[&str]) -> Result<(), KeymapError> {
+        let mut keycodes = Vec::new();
+        for name in names {
+            match Keycode::from_name(name) {
+                Some(keycode) => keycodes.push(keycode),
+                None => {
+                    return Err(KeymapError::UnknownKey {
+                        chip8_key,
+                        name: name.to_string(),
+                    })
+                }
+            }
+        }
+        self.keys.retain(|_, k| *k != chip8_key);
+        for keycode in keycodes {
+            self.keys.insert(keycode, chip8_key);
+        }
+        Ok(())
+    }
+    /// Binds a CHIP-8 key to a controller button, or to an axis direction
+    /// named like `leftx-`, also keeping its other bindings if `add`
+    fn bind_button(&mut self, chip8_key: u8, name: &str, add: bool) -> Result<(), KeymapError> {
+        let unknown = || KeymapError::UnknownButton {
+            chip8_key,
+            name: name.to_string(),
+        };
+        let axis = if let Some(axis) = name.strip_suffix('+') {
+            Some((Axis::from_string(axis).ok_or_else(unknown)?, true))
+        } else if let Some(axis) = name.strip_suffix('-') {
+            Some((Axis::from_string(axis).ok_or_else(unknown)?, false))
+        } else {
+            None
+        };
+        let button = match axis {
+            Some(_) => None,
+            None => Some(Button::from_string(name).ok_or_else(unknown)?),
+        };
+        if !add {
+            self.buttons.retain(|_, k| *k != chip8_key);
+            self.axes.retain(|_, k| *k != chip8_key);
+        }
+        if let Some(axis) = axis {
+            self.axes.insert(axis, chip8_key);
+        }
+        if let Some(button) = button {
+            self.buttons.insert(button, chip8_key);
+        }
+        Ok(())
+    }
+    /// CHIP-8 key bound to a keyboard key
+    pub fn get(&self, keycode: Keycode) -> Option<u8> {
+        self.keys.get(&keycode).copied()
+    }
+    /// Sets the CHIP-8 keys held on a controller
+    pub fn controller_keys(&self, controller: &GameController, keys: &mut [u8; 16]) {
+        for (button, k) in &self.buttons {
+            if controller.button(*button) {
+                keys[*k as usize] = 1;
+            }
+        }
+        for ((axis, positive), k) in &self.axes {
+            // i32 as -i16::MIN does not fit in an i16
+            let value = controller.axis(*axis) as i32;
+            let value = if *positive { value } else { -value };
+            if value > self.deadzone as i32 {
+                keys[*k as usize] = 1;
+            }
+        }
+    }
+}
+
+/// Parses a binding of a CHIP-8 key, given as a hex digit, to one or more
+/// names
+fn bindings<'a>(chip8_key: &str, names: &'a Value) -> Result<(u8, Vec<&'a str>), KeymapError> {
+    let k = match u8::from_str_radix(chip8_key, 16) {
+        Ok(k) if chip8_key.len() == 1 => k,
+        _ => return Err(KeymapError::InvalidChip8Key(chip8_key.to_string())),
+    };
+    let invalid = || {
+        KeymapError::Invalid(format!(
+            "CHIP-8 key {:X} must be bound to a name or a list of them",
+            k
+        ))
+    };
+    let names = match names {
+        Value::String(name) => vec![name.as_str()],
+        Value::Array(names) => names
+            .iter()
+            .map(|name| name.as_str().ok_or_else(invalid))
+            .collect::<Result<_, _>>()?,
+        _ => return Err(invalid()),
+    };
+    Ok((k, names))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(keymap: &Keymap, names: &[&str]) -> Vec<Option<u8>> {
+        names
+            .iter()
+            .map(|name| keymap.get(Keycode::from_name(name).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn presets() {
+        let keymap = Keymap::preset("qwerty").unwrap();
+        assert_eq!(
+            keys(&keymap, &["1", "4", "Q", "R", "S", "Z", "X", "V", "5"]),
+            [
+                Some(0x1),
+                Some(0xC),
+                Some(0x4),
+                Some(0xD),
+                Some(0x8),
+                Some(0xA),
+                Some(0x0),
+                Some(0xF),
+                None
+            ]
+        );
+        let keymap = Keymap::preset("azerty").unwrap();
+        assert_eq!(
+            keys(&keymap, &["A", "Z", "Q", "W"]),
+            [Some(0x4), Some(0x5), Some(0x7), Some(0xA)]
+        );
+        let keymap = Keymap::preset("dvorak").unwrap();
+        assert_eq!(
+            keys(&keymap, &["'", ",", "O", ";", "K"]),
+            [Some(0x4), Some(0x5), Some(0x8), Some(0xA), Some(0xF)]
+        );
+        assert_eq!(keys(&Keymap::default(), &["Q"]), [Some(0x4)]);
+        assert!(matches!(
+            Keymap::preset("colemak"),
+            Err(KeymapError::UnknownPreset(name)) if name == "colemak"
+        ));
+    }
+
+    #[test]
+    fn config() {
+        let config = r#"
+            preset = "azerty"
+
+            [keys]
+            5 = ["Up", "K"]
+
+            [roms."pong.c8"]
+            preset = "qwerty"
+            keys = { 1 = "Up", 4 = ["Left", "Q"] }
+        "#;
+        // the preset and keys apply to every rom
+        let keymap = Keymap::from_config(config, "other.c8").unwrap();
+        assert_eq!(
+            keys(&keymap, &["A", "Z", "Up", "K", "1"]),
+            [Some(0x4), None, Some(0x5), Some(0x5), Some(0x1)]
+        );
+        // the rom section starts again from its preset
+        let keymap = Keymap::from_config(config, "pong.c8").unwrap();
+        assert_eq!(
+            keys(&keymap, &["Q", "W", "Up", "1", "Left", "A", "K"]),
+            [
+                Some(0x4),
+                Some(0x5),
+                Some(0x1),
+                None,
+                Some(0x4),
+                Some(0x7),
+                None
+            ]
+        );
+        let keymap = Keymap::from_config("", "pong.c8").unwrap();
+        assert_eq!(keys(&keymap, &["Q"]), [Some(0x4)]);
+    }
+
+    #[test]
+    fn config_errors() {
+        let error = |config: &str| Keymap::from_config(config, "pong.c8").unwrap_err();
+        assert!(matches!(
+            error(r#"preset = "colemak""#),
+            KeymapError::UnknownPreset(name) if name == "colemak"
+        ));
+        assert!(matches!(
+            error(r#"[roms."pong.c8"]
+                     preset = "bepo""#),
+            KeymapError::UnknownPreset(name) if name == "bepo"
+        ));
+        assert!(matches!(
+            error(r#"keys = { 5 = ["Up", "Nope"] }"#),
+            KeymapError::UnknownKey { chip8_key: 5, name } if name == "Nope"
+        ));
+        assert!(matches!(
+            error(r#"keys = { G = "Up" }"#),
+            KeymapError::InvalidChip8Key(key) if key == "G"
+        ));
+        assert!(matches!(
+            error(r#"gamepad = { 5 = "leftz+" }"#),
+            KeymapError::UnknownButton { chip8_key: 5, name } if name == "leftz+"
+        ));
+        assert!(matches!(error("keys = { 5 = 1 }"), KeymapError::Invalid(_)));
+        assert!(matches!(
+            error("gamepad = { deadzone = 1.5 }"),
+            KeymapError::Invalid(_)
+        ));
+        assert!(matches!(
+            error("layout = \"qwerty\""),
+            KeymapError::Invalid(_)
+        ));
+        assert!(matches!(
+            error("[roms.\"pong.c8\"]\nroms = {}"),
+            KeymapError::Invalid(_)
+        ));
+        assert!(matches!(error("keys = ["), KeymapError::Toml(_)));
+        assert_eq!(
+            error(r#"keys = { A = "Nope" }"#).to_string(),
+            "unknown keyboard key 'Nope' for CHIP-8 key A, \
+             the names are listed in https://wiki.libsdl.org/SDL_Keycode"
+        );
+    }
+}
@@ -1,129 +1,38 @@
-use std::collections::HashMap;
-use std::{thread, time};
-extern crate sdl2;
-
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
-use sdl2::rect::Rect;
-use sdl2::render::Canvas;
-
-use clap::{App, Arg};
-
-use chip8::Chip8;
-
-struct SdlEmulator {
-    w: usize,
-    h: usize,
-    zoom: usize,
-    sdl_context: sdl2::Sdl,
-    canvas: Canvas<sdl2::video::Window>,
-    vkeys: HashMap<Keycode, u8>,
-    chip8: Chip8,
-}
+use std::fs;
+use std::path::{Path, PathBuf};
 
-impl SdlEmulator {
-    fn new(w: usize, h: usize, zoom: usize) -> SdlEmulator {
-        let mut c = Chip8::new();
-
-        let sdl_context = sdl2::init().unwrap();
-        let video_subsystem = sdl_context.video().unwrap();
-
-        let window = video_subsystem
-            .window("chip8-rs", (w * zoom) as u32, (h * zoom) as u32)
-            .position_centered()
-            .build()
-            .unwrap();
-
-        let mut canvas = window.into_canvas().build().unwrap();
-
-        let mut vkeys: HashMap<Keycode, u8> = HashMap::new();
-        vkeys.insert(Keycode::Num1, 0x01);
-        vkeys.insert(Keycode::Num2, 0x02);
-        vkeys.insert(Keycode::Num3, 0x03);
-        vkeys.insert(Keycode::Num4, 0x0c);
-        vkeys.insert(Keycode::Q, 0x04);
-        vkeys.insert(Keycode::W, 0x05);
-        vkeys.insert(Keycode::E, 0x06);
-        vkeys.insert(Keycode::R, 0x0d);
-        vkeys.insert(Keycode::A, 0x07);
-        vkeys.insert(Keycode::S, 0x08);
-        vkeys.insert(Keycode::D, 0x09);
-        vkeys.insert(Keycode::F, 0x0e);
-        vkeys.insert(Keycode::Z, 0x0a);
-        vkeys.insert(Keycode::X, 0x00);
-        vkeys.insert(Keycode::C, 0x0b);
-        vkeys.insert(Keycode::V, 0x0f);
-
-        SdlEmulator {
-            w,
-            h,
-            zoom,
-            sdl_context,
-            canvas,
-            vkeys,
-            chip8: c,
-        }
-    }
-    fn draw_graphics(&mut self) {
-        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
-        self.canvas.clear();
-        self.canvas.set_draw_color(Color::RGB(255, 255, 255));
-        for y in 0..self.h {
-            for x in 0..self.w {
-                let pixel = self.chip8.gfx[y * self.w + x];
-                if pixel != 0 {
-                    self.canvas.fill_rect(Rect::new(
-                        (x * self.zoom) as i32,
-                        (y * self.zoom) as i32,
-                        (self.zoom) as u32,
-                        (self.zoom) as u32,
-                    ));
-                }
-            }
-        }
+use clap::{App, Arg, ArgMatches, SubCommand};
 
-        self.canvas.present();
-        self.chip8.draw_flag = false;
-    }
-    fn set_keys(&mut self) -> Result<(), String> {
-        let mut events = self.sdl_context.event_pump()?;
-        for event in events.poll_iter() {
-            match event {
-                Event::Quit { .. } => {
-                    println!("Quit");
-                    std::process::exit(0);
-                }
-                Event::KeyDown {
-                    keycode: Some(keycode),
-                    ..
-                } => {
-                    println!("k {:?}", keycode);
-                    if self.vkeys.contains_key(&keycode) {
-                        let k_hex = self.vkeys.get(&keycode).unwrap();
-                        self.chip8.key[*k_hex as usize] = 1;
-                    }
-                }
-                Event::KeyUp {
-                    keycode: Some(keycode),
-                    ..
-                } => {
-                    println!("k {:?}", keycode);
-                    if self.vkeys.contains_key(&keycode) {
-                        let k_hex = self.vkeys.get(&keycode).unwrap();
-                        self.chip8.key[*k_hex as usize] = 0;
-                    }
-                    if keycode == Keycode::Escape {
-                        println!("EXIT");
-                        std::process::exit(0);
-                    }
-                }
-                _ => {}
-            }
-        }
-        Ok(())
-    }
-}
+use chip8::movie::Movie;
+use chip8::{asm, disasm, Backend, Chip8, Instruction, Platform, Quirks, PROGRAM_START};
+
+mod capture;
+mod headless;
+mod input;
+mod palette;
+use capture::{CaptureOptions, Clip};
+use headless::Limit;
+use input::{InputSource, Live, Recorder, Replay};
+use palette::Palette;
+
+#[cfg(feature = "sdl")]
+mod audio;
+#[cfg(any(feature = "sdl", test))]
+mod debugger;
+#[cfg(feature = "sdl")]
+mod gamepad;
+#[cfg(feature = "sdl")]
+mod keymap;
+#[cfg(any(feature = "sdl", test))]
+mod persistence;
+#[cfg(feature = "sdl")]
+mod sdl_emulator;
+#[cfg(feature = "sdl")]
+use audio::AudioOptions;
+#[cfg(feature = "sdl")]
+use keymap::Keymap;
+#[cfg(feature = "sdl")]
+use sdl_emulator::{Options, SdlEmulator};
 
 fn main() {
     let matches = App::new("chip8-rs")
@@ -136,23 +45,522 @@ fn main() {
                 .takes_value(true)
                 .help("File path of the rom to load"),
         )
+        .arg(
+            Arg::with_name("platform")
+                .long("platform")
+                .takes_value(true)
+                .possible_values(&["chip8", "schip", "xochip"])
+                .default_value("chip8")
+                .help("Platform the rom was written for"),
+        )
+        .arg(
+            Arg::with_name("quirks")
+                .long("quirks")
+                .takes_value(true)
+                .possible_values(&["vip", "chip48", "schip", "xochip", "modern"])
+                .help("Interpreter quirks expected by the rom [default: the platform ones]"),
+        )
+        .arg(
+            Arg::with_name("backend")
+                .long("backend")
+                .takes_value(true)
+                .possible_values(&["interpreter", "cached"])
+                .default_value("interpreter")
+                .help("How instructions are decoded, cached is faster on long runs"),
+        )
+        .arg(
+            Arg::with_name("ipf")
+                .long("ipf")
+                .takes_value(true)
+                .default_value("11")
+                .help("Instructions executed per frame, at 60 frames per second"),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .takes_value(true)
+                .help("Seed of the random numbers, to reproduce a run [default: from the clock]"),
+        )
+        .arg(
+            Arg::with_name("volume")
+                .long("volume")
+                .takes_value(true)
+                .default_value("25")
+                .help("Volume of the buzzer, from 0 to 100"),
+        )
+        .arg(
+            Arg::with_name("frequency")
+                .long("frequency")
+                .takes_value(true)
+                .default_value("440")
+                .help("Frequency of the buzzer, in Hz"),
+        )
+        .arg(
+            Arg::with_name("mute")
+                .long("mute")
+                .help("Disables the sound"),
+        )
+        .arg(
+            Arg::with_name("scale")
+                .long("scale")
+                .takes_value(true)
+                .default_value("8")
+                .help("Initial size of the 64x32 pixels of the display, the window can be resized"),
+        )
+        .arg(
+            Arg::with_name("integer-scale")
+                .long("integer-scale")
+                .help("Scales the display by whole numbers only, so all its pixels have the same size"),
+        )
+        .arg(
+            Arg::with_name("palette")
+                .long("palette")
+                .takes_value(true)
+                .default_value("mono")
+                .help(
+                    "Display colors: mono, amber, green, octo, or four comma separated hex \
+                     colors for the off pixels, the first plane, the second plane and both",
+                ),
+        )
+        .arg(
+            Arg::with_name("fg")
+                .long("fg")
+                .takes_value(true)
+                .help("Color of the lit pixels in hex, like #FFB000 [default: the palette one]"),
+        )
+        .arg(
+            Arg::with_name("bg")
+                .long("bg")
+                .takes_value(true)
+                .help("Color of the off pixels in hex [default: the palette one]"),
+        )
+        .arg(
+            Arg::with_name("grid")
+                .long("grid")
+                .help("Draws lines between the pixels"),
+        )
+        .arg(
+            Arg::with_name("screenshot")
+                .long("screenshot")
+                .takes_value(true)
+                .help("PNG file where to write the display at the end, with the scale and colors of the window"),
+        )
+        .arg(
+            Arg::with_name("capture")
+                .long("capture")
+                .takes_value(true)
+                .help("File where to record every frame, as an animated GIF if it ends with .gif or else as raw RGB24 frames"),
+        )
+        .arg(
+            Arg::with_name("persistence")
+                .long("persistence")
+                .takes_value(true)
+                .possible_values(&["off", "fade", "or"])
+                .default_value("off")
+                .help(
+                    "Against flicker, pixels going off fade out, or stay lit for one more \
+                     frame. F2 changes it while playing",
+                ),
+        )
+        .arg(
+            Arg::with_name("keymap")
+                .long("keymap")
+                .takes_value(true)
+                .help(
+                "Keyboard layout: qwerty, azerty, dvorak, or a TOML config file of keyboard and gamepad bindings [default: qwerty]",
+            ),
+        )
+        .arg(
+            Arg::with_name("rewind")
+                .long("rewind")
+                .takes_value(true)
+                .default_value("10")
+                .help("Seconds of gameplay kept to play back in reverse holding backspace"),
+        )
+        .arg(
+            Arg::with_name("record")
+                .long("record")
+                .takes_value(true)
+                .help("File where to record the keys pressed on each frame, as a movie"),
+        )
+        .arg(
+            Arg::with_name("replay")
+                .long("replay")
+                .takes_value(true)
+                .conflicts_with("record")
+                .help("Movie to replay, its platform, quirks, seed and ipf override the options"),
+        )
+        .arg(
+            Arg::with_name("debug")
+                .long("debug")
+                .conflicts_with_all(&["headless", "record", "replay"])
+                .help("Starts paused under the debugger, which reads commands from stdin"),
+        )
+        .arg(
+            Arg::with_name("headless")
+                .long("headless")
+                .help("Runs without a window and prints the final display"),
+        )
+        .arg(
+            Arg::with_name("cycles")
+                .long("cycles")
+                .takes_value(true)
+                .help("Instructions to execute in headless mode"),
+        )
+        .arg(
+            Arg::with_name("frames")
+                .long("frames")
+                .takes_value(true)
+                .conflicts_with("cycles")
+                .help("Frames to run in headless mode"),
+        )
+        .arg(
+            Arg::with_name("state")
+                .long("state")
+                .takes_value(true)
+                .help(
+                "File where to write the final registers as JSON in headless mode, - for stdout",
+            ),
+        )
+        .subcommand(
+            SubCommand::with_name("disasm")
+                .about("Prints the disassembly of a rom")
+                .arg(
+                    Arg::with_name("rom")
+                        .required(true)
+                        .help("File path of the rom to disassemble"),
+                )
+                .arg(
+                    Arg::with_name("linear")
+                        .long("linear")
+                        .help("Decodes every two bytes instead of following the jumps and calls"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("asm")
+                .about("Assembles an Octo source file into a rom")
+                .arg(
+                    Arg::with_name("source")
+                        .required(true)
+                        .help("File path of the source to assemble"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .takes_value(true)
+                        .help("File where to write the rom [default: the source with .ch8]"),
+                ),
+        )
         .get_matches();
+    if let Some(matches) = matches.subcommand_matches("disasm") {
+        run_disasm(matches);
+        return;
+    }
+    if let Some(matches) = matches.subcommand_matches("asm") {
+        run_asm(matches);
+        return;
+    }
     let file = matches.value_of("file");
     let file = match file {
         Some(file) => file,
-        _ => panic!("Please specify file path of the rom to load"),
+        _ => arg_error("Please specify file path of the rom to load"),
+    };
+    let platform: Platform = matches.value_of("platform").unwrap().parse().unwrap();
+    let quirks: Quirks = match matches.value_of("quirks") {
+        Some(quirks) => quirks.parse().unwrap(),
+        None => platform.quirks(),
+    };
+    let ipf: usize = match matches.value_of("ipf").unwrap().parse() {
+        Ok(ipf) if ipf > 0 => ipf,
+        _ => arg_error("--ipf must be a positive number"),
+    };
+
+    let (c, ipf, input): (Chip8, usize, Box<dyn InputSource>) = match matches.value_of("replay") {
+        Some(path) => {
+            let movie = read_movie(path);
+            let rom = match fs::read(file) {
+                Ok(rom) => rom,
+                Err(err) => {
+                    eprintln!("Error loading {}: {}", file, err);
+                    std::process::exit(1);
+                }
+            };
+            match movie.machine(&rom) {
+                Ok(c) => (c, movie.ipf as usize, Box::new(Replay::new(movie))),
+                Err(err) => {
+                    eprintln!("Error replaying {}: {}", path, err);
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => {
+            let mut c = Chip8::with_platform(platform, quirks);
+            if let Some(seed) = matches.value_of("seed") {
+                match seed.parse() {
+                    Ok(seed) => c = c.with_rng(seed),
+                    _ => arg_error("--seed must be a number"),
+                }
+            }
+            if let Err(err) = c.load_game(file) {
+                eprintln!("Error loading {}: {}", file, err);
+                std::process::exit(1);
+            }
+            (c, ipf, Box::new(Live))
+        }
+    };
+    let backend: Backend = matches.value_of("backend").unwrap().parse().unwrap();
+    let c = c.with_backend(backend);
+    let recorder = matches
+        .value_of("record")
+        .map(|path| Recorder::new(Movie::new(&c, ipf as u32), Path::new(path)));
+    let capture = capture_options(&matches, c.platform());
+    let clip =
+        matches
+            .value_of("capture")
+            .map(|path| match Clip::create(Path::new(path), capture) {
+                Ok(clip) => clip,
+                Err(err) => {
+                    eprintln!("Error writing {}: {}", path, err);
+                    std::process::exit(1);
+                }
+            });
+
+    if matches.is_present("headless") {
+        run_headless(c, ipf, input, recorder, clip, &matches);
+    } else {
+        run_sdl(c, ipf, input, recorder, clip, &matches);
+    }
+}
+
+/// Reports an invalid command line argument and exits
+fn arg_error(message: &str) -> ! {
+    eprintln!("Error: {}", message);
+    std::process::exit(1);
+}
+
+/// Scale and colors of the display, in the window and in captures
+fn capture_options(matches: &ArgMatches, platform: Platform) -> CaptureOptions {
+    let mut palette: Palette = match matches.value_of("palette").unwrap().parse() {
+        Ok(palette) => palette,
+        Err(err) => arg_error(&format!("--palette: {}", err)),
+    };
+    for (name, i) in [("bg", 0), ("fg", 1)] {
+        if let Some(color) = matches.value_of(name) {
+            match color.parse() {
+                Ok(color) => palette.colors[i] = color,
+                Err(err) => arg_error(&format!("--{}: {}", name, err)),
+            }
+        }
+    }
+    CaptureOptions {
+        palette,
+        scale: match matches.value_of("scale").unwrap().parse() {
+            Ok(scale) if scale > 0 => scale,
+            _ => arg_error("--scale must be a positive number"),
+        },
+        grid: matches.is_present("grid"),
+        hires: Instruction::Hires.is_supported(platform),
+    }
+}
+
+fn read_movie(path: &str) -> Movie {
+    let movie = fs::read(path)
+        .map_err(|err| err.to_string())
+        .and_then(|b| Movie::from_bytes(&b).map_err(|err| err.to_string()));
+    match movie {
+        Ok(movie) => movie,
+        Err(err) => {
+            eprintln!("Error loading {}: {}", path, err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run_disasm(matches: &ArgMatches) {
+    let path = matches.value_of("rom").unwrap();
+    let rom = match fs::read(path) {
+        Ok(rom) => rom,
+        Err(err) => {
+            eprintln!("Error loading {}: {}", path, err);
+            std::process::exit(1);
+        }
+    };
+    let d = disasm::disassemble(&rom, PROGRAM_START as u16, matches.is_present("linear"));
+    print!("{}", d.listing());
+}
+
+fn run_asm(matches: &ArgMatches) {
+    let path = matches.value_of("source").unwrap();
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("Error loading {}: {}", path, err);
+            std::process::exit(1);
+        }
+    };
+    let rom = match asm::assemble(&source) {
+        Ok(rom) => rom,
+        Err(err) => {
+            eprintln!("Error assembling {}: {}", path, err);
+            std::process::exit(1);
+        }
+    };
+    let output = match matches.value_of("output") {
+        Some(output) => PathBuf::from(output),
+        None => Path::new(path).with_extension("ch8"),
     };
-    println!("{:?}", file);
+    if let Err(err) = fs::write(&output, &rom) {
+        eprintln!("Error writing {}: {}", output.display(), err);
+        std::process::exit(1);
+    }
+}
 
-    let mut e = SdlEmulator::new(64, 32, 8);
-    e.chip8.load_game(file);
+fn run_headless(
+    mut c: Chip8,
+    ipf: usize,
+    mut input: Box<dyn InputSource>,
+    mut recorder: Option<Recorder>,
+    mut clip: Option<Clip>,
+    matches: &ArgMatches,
+) {
+    let parse_limit = |name: &str| -> u64 {
+        match matches.value_of(name).unwrap().parse() {
+            Ok(n) => n,
+            _ => arg_error(&format!("--{} must be a number", name)),
+        }
+    };
+    let limit = if matches.is_present("cycles") {
+        Limit::Cycles(parse_limit("cycles"))
+    } else if matches.is_present("frames") {
+        Limit::Frames(parse_limit("frames"))
+    } else if let Some(path) = matches.value_of("replay") {
+        Limit::Frames(read_movie(path).frames.len() as u64)
+    } else {
+        arg_error("--headless needs --cycles, --frames or --replay");
+    };
 
-    loop {
-        e.chip8.emulate_cycle();
-        if e.chip8.draw_flag {
-            e.draw_graphics();
+    let summary = headless::run(
+        &mut c,
+        ipf,
+        limit,
+        input.as_mut(),
+        recorder.as_mut(),
+        clip.as_mut(),
+    );
+    if let Some(recorder) = &recorder {
+        if let Err(err) = recorder.save() {
+            eprintln!("Error writing {}: {}", recorder.path().display(), err);
+            std::process::exit(1);
         }
-        e.set_keys();
-        std::thread::sleep(time::Duration::from_millis(200 / 60));
+        // stdout has the display
+        eprintln!(
+            "Recorded {} frames to {}",
+            recorder.frames(),
+            recorder.path().display()
+        );
     }
+    if let Some(clip) = clip {
+        let (frames, path) = (clip.frames(), clip.path().to_path_buf());
+        if let Some(err) = &summary.clip_error {
+            eprintln!("Error writing {}: {}", path.display(), err);
+            std::process::exit(1);
+        }
+        if let Err(err) = clip.finish() {
+            eprintln!("Error writing {}: {}", path.display(), err);
+            std::process::exit(1);
+        }
+        eprintln!("Captured {} frames to {}", frames, path.display());
+    }
+    if let Some(path) = matches.value_of("screenshot") {
+        if let Err(err) =
+            capture::screenshot(&c, &capture_options(matches, c.platform()), Path::new(path))
+        {
+            eprintln!("Error writing {}: {}", path, err);
+            std::process::exit(1);
+        }
+    }
+    print!("{}", headless::ascii_screen(&c));
+    if let Some(path) = matches.value_of("state") {
+        let json = headless::state_json(&c, &summary);
+        if path == "-" {
+            print!("{}", json);
+        } else if let Err(err) = fs::write(path, json) {
+            eprintln!("Error writing {}: {}", path, err);
+            std::process::exit(1);
+        }
+    }
+    if let Err(err) = summary.outcome {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(feature = "sdl")]
+fn run_sdl(
+    c: Chip8,
+    ipf: usize,
+    input: Box<dyn InputSource>,
+    recorder: Option<Recorder>,
+    clip: Option<Clip>,
+    matches: &ArgMatches,
+) {
+    let audio = AudioOptions {
+        volume: match matches.value_of("volume").unwrap().parse() {
+            Ok(volume) if volume <= 100 => volume,
+            _ => arg_error("--volume must be a number from 0 to 100"),
+        },
+        frequency: match matches.value_of("frequency").unwrap().parse() {
+            Ok(frequency) if frequency > 0.0 => frequency,
+            _ => arg_error("--frequency must be a positive number"),
+        },
+        mute: matches.is_present("mute"),
+    };
+    let rewind_seconds: usize = match matches.value_of("rewind").unwrap().parse() {
+        Ok(seconds) => seconds,
+        _ => arg_error("--rewind must be a number of seconds"),
+    };
+    let rom_path = Path::new(matches.value_of("file").unwrap());
+    let keymap = match matches.value_of("keymap") {
+        Some(arg) => match Keymap::from_arg(arg, rom_path) {
+            Ok(keymap) => keymap,
+            Err(err) => {
+                eprintln!("Error loading keymap {}: {}", arg, err);
+                std::process::exit(1);
+            }
+        },
+        None => Keymap::default(),
+    };
+    let opts = Options {
+        capture: capture_options(matches, c.platform()),
+        integer_scale: matches.is_present("integer-scale"),
+        debug: matches.is_present("debug"),
+        rewind_frames: rewind_seconds * 60,
+        keymap,
+        persistence: matches.value_of("persistence").unwrap().parse().unwrap(),
+        clip,
+        screenshot: matches
+            .value_of("screenshot")
+            .map(|path| Path::new(path).to_path_buf()),
+    };
+
+    let replaying = matches.is_present("replay");
+    let mut e = SdlEmulator::new(c, rom_path, opts, &audio, input, recorder, replaying);
+    if let Err(err) = e.run(ipf) {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(feature = "sdl"))]
+fn run_sdl(
+    _c: Chip8,
+    _ipf: usize,
+    _input: Box<dyn InputSource>,
+    _recorder: Option<Recorder>,
+    _clip: Option<Clip>,
+    _matches: &ArgMatches,
+) {
+    eprintln!("chip8-rs was built without the sdl feature, only --headless is available");
+    std::process::exit(1);
 }
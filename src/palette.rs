@@ -0,0 +1,214 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// An RGB color, written in hex like `#FFB000`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl Rgb {
+    /// The color a fraction of the way towards another one
+    pub fn mix(self, other: Rgb, fraction: f32) -> Rgb {
+        let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * fraction).round() as u8;
+        Rgb(
+            mix(self.0, other.0),
+            mix(self.1, other.1),
+            mix(self.2, other.2),
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidColor(String);
+
+impl fmt::Display for InvalidColor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid color '{}', expected hex like #FFB000", self.0)
+    }
+}
+
+impl std::error::Error for InvalidColor {}
+
+impl FromStr for Rgb {
+    type Err = InvalidColor;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        // from_str_radix also takes a sign
+        if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(InvalidColor(s.to_string()));
+        }
+        let n = u32::from_str_radix(hex, 16).unwrap();
+        Ok(Rgb((n >> 16) as u8, (n >> 8) as u8, n as u8))
+    }
+}
+
+/// Colors of the pixels by value of their planes: off, first plane, second
+/// plane and both. Only XO-CHIP roms draw on the second plane
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub colors: [Rgb; 4],
+}
+
+const PRESETS: [(&str, [Rgb; 4]); 4] = [
+    (
+        "mono",
+        [
+            Rgb(0, 0, 0),
+            Rgb(255, 255, 255),
+            Rgb(170, 170, 170),
+            Rgb(85, 85, 85),
+        ],
+    ),
+    (
+        "amber",
+        [
+            Rgb(0x1A, 0x0F, 0x00),
+            Rgb(0xFF, 0xB0, 0x00),
+            Rgb(0x99, 0x55, 0x00),
+            Rgb(0xFF, 0xE0, 0x99),
+        ],
+    ),
+    (
+        "green",
+        [
+            Rgb(0x0A, 0x14, 0x0A),
+            Rgb(0x33, 0xFF, 0x66),
+            Rgb(0x1A, 0x88, 0x33),
+            Rgb(0xBB, 0xFF, 0xCC),
+        ],
+    ),
+    // the default colors of the Octo IDE
+    (
+        "octo",
+        [
+            Rgb(0x99, 0x66, 0x00),
+            Rgb(0xFF, 0xCC, 0x00),
+            Rgb(0xFF, 0x66, 0x00),
+            Rgb(0x66, 0x22, 0x00),
+        ],
+    ),
+];
+
+impl Palette {
+    /// Color of the lines between the pixels when the grid is drawn
+    pub fn grid(&self) -> Rgb {
+        self.colors[0].mix(self.colors[1], 0.2)
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette {
+            colors: PRESETS[0].1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownPalette(String);
+
+impl fmt::Display for UnknownPalette {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "unknown palette '{}', expected one of: mono, amber, green, octo, \
+             or four comma separated colors like #000000,#FFFFFF,#AAAAAA,#555555",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for UnknownPalette {}
+
+impl FromStr for Palette {
+    type Err = UnknownPalette;
+
+    /// A preset name, or the four colors separated by commas
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((_, colors)) = PRESETS.iter().find(|(name, _)| *name == s) {
+            return Ok(Palette { colors: *colors });
+        }
+        let colors = s
+            .split(',')
+            .map(|c| c.trim().parse())
+            .collect::<Result<Vec<Rgb>, _>>();
+        match colors {
+            Ok(colors) if colors.len() == 4 => Ok(Palette {
+                colors: [colors[0], colors[1], colors[2], colors[3]],
+            }),
+            _ => Err(UnknownPalette(s.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rgb() {
+        assert_eq!("#FFB000".parse(), Ok(Rgb(0xFF, 0xB0, 0x00)));
+        assert_eq!("1a0f00".parse(), Ok(Rgb(0x1A, 0x0F, 0x00)));
+        for s in ["", "#", "#FFF", "#FFB0000", "#GGGGGG", "##FFB000", "+FFB00"] {
+            assert_eq!(s.parse::<Rgb>(), Err(InvalidColor(s.to_string())));
+        }
+        assert_eq!(
+            "red".parse::<Rgb>().unwrap_err().to_string(),
+            "invalid color 'red', expected hex like #FFB000"
+        );
+    }
+
+    #[test]
+    fn mix() {
+        let (black, white) = (Rgb(0, 0, 0), Rgb(255, 255, 255));
+        assert_eq!(black.mix(white, 0.0), black);
+        assert_eq!(black.mix(white, 1.0), white);
+        assert_eq!(black.mix(white, 0.2), Rgb(51, 51, 51));
+        assert_eq!(
+            Rgb(200, 100, 0).mix(Rgb(100, 100, 255), 0.5),
+            Rgb(150, 100, 128)
+        );
+        assert_eq!(Palette::default().grid(), Rgb(51, 51, 51));
+    }
+
+    #[test]
+    fn palettes() {
+        assert_eq!("mono".parse(), Ok(Palette::default()));
+        assert_eq!(
+            "amber".parse::<Palette>().unwrap().colors[1],
+            Rgb(0xFF, 0xB0, 0x00)
+        );
+        assert_eq!(
+            "green".parse::<Palette>().unwrap().colors[0],
+            Rgb(0x0A, 0x14, 0x0A)
+        );
+        assert_eq!(
+            "octo".parse::<Palette>().unwrap().colors[3],
+            Rgb(0x66, 0x22, 0x00)
+        );
+        assert_eq!(
+            "#000000, #FFFFFF,#aaaaaa,555555".parse(),
+            Ok(Palette {
+                colors: [
+                    Rgb(0, 0, 0),
+                    Rgb(255, 255, 255),
+                    Rgb(170, 170, 170),
+                    Rgb(85, 85, 85)
+                ]
+            })
+        );
+        for s in [
+            "Mono",
+            "#000000,#FFFFFF,#AAAAAA",
+            "#000000,#FFFFFF,#AAAAAA,#555555,#000000",
+            "#000000,#FFFFFF,#AAAAAA,#55555",
+        ] {
+            assert_eq!(s.parse::<Palette>(), Err(UnknownPalette(s.to_string())));
+        }
+        assert_eq!(
+            "sepia".parse::<Palette>().unwrap_err().to_string(),
+            "unknown palette 'sepia', expected one of: mono, amber, green, octo, \
+             or four comma separated colors like #000000,#FFFFFF,#AAAAAA,#555555"
+        );
+    }
+}
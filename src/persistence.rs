@@ -0,0 +1,183 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+/// Frames a pixel takes to fade out once it goes off
+const FADE_FRAMES: usize = 4;
+
+/// How pixels going off are shown. Most roms erase their sprites and draw
+/// them again every frame, which flickers unless the pixels persist a bit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Persistence {
+    /// Pixels go off at once, like the display says
+    Off,
+    /// Pixels fade out over a few frames, like on a phosphor screen
+    Fade,
+    /// Pixels lit on either of the last two frames are shown
+    Or,
+}
+
+impl Persistence {
+    /// The mode after this one, for cycling through them with a hotkey
+    pub fn next(self) -> Persistence {
+        match self {
+            Persistence::Off => Persistence::Fade,
+            Persistence::Fade => Persistence::Or,
+            Persistence::Or => Persistence::Off,
+        }
+    }
+}
+
+impl fmt::Display for Persistence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Persistence::Off => write!(f, "off"),
+            Persistence::Fade => write!(f, "fade"),
+            Persistence::Or => write!(f, "or"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownPersistence(String);
+
+impl fmt::Display for UnknownPersistence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "unknown persistence '{}', expected one of: off, fade, or",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for UnknownPersistence {}
+
+impl FromStr for Persistence {
+    type Err = UnknownPersistence;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Persistence::Off),
+            "fade" => Ok(Persistence::Fade),
+            "or" => Ok(Persistence::Or),
+            _ => Err(UnknownPersistence(s.to_string())),
+        }
+    }
+}
+
+/// The display of the last frames, newest first, from which the pixels
+/// shown are worked out
+#[derive(Default)]
+pub struct FrameHistory {
+    frames: VecDeque<Vec<u8>>,
+}
+
+impl FrameHistory {
+    /// Adds the display of a frame. The frames of another resolution are
+    /// dropped, they don't line up with it
+    pub fn push(&mut self, gfx: &[u8]) {
+        if self.frames.front().is_some_and(|f| f.len() != gfx.len()) {
+            self.frames.clear();
+        }
+        // reuse the buffer of the frame falling out of the history
+        let mut frame = if self.frames.len() > FADE_FRAMES {
+            self.frames.pop_back().unwrap()
+        } else {
+            Vec::with_capacity(gfx.len())
+        };
+        frame.clear();
+        frame.extend_from_slice(gfx);
+        self.frames.push_front(frame);
+    }
+    /// Planes of the pixel `i` to show, with the brightness of their color
+    /// from 0 (the background color) to 1
+    pub fn pixel(&self, persistence: Persistence, i: usize) -> (u8, f32) {
+        let current = self.frames.front().map_or(0, |f| f[i]);
+        match persistence {
+            Persistence::Off => (current, 1.0),
+            Persistence::Or => (current | self.frames.get(1).map_or(0, |f| f[i]), 1.0),
+            Persistence::Fade => {
+                // the newest frame the pixel was lit on, dimmer the older
+                // it is
+                for (age, frame) in self.frames.iter().enumerate() {
+                    if frame[i] != 0 {
+                        let brightness = 1.0 - age as f32 / (FADE_FRAMES + 1) as f32;
+                        return (frame[i], brightness);
+                    }
+                }
+                (0, 1.0)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The pixels of every mode, with the brightness in fifths
+    fn pixels(history: &FrameHistory, i: usize) -> [(u8, u32); 3] {
+        [Persistence::Off, Persistence::Or, Persistence::Fade].map(|p| {
+            let (planes, brightness) = history.pixel(p, i);
+            (planes, (brightness * 5.0).round() as u32)
+        })
+    }
+
+    #[test]
+    fn modes() {
+        assert_eq!("fade".parse(), Ok(Persistence::Fade));
+        assert_eq!(
+            "dim".parse::<Persistence>().unwrap_err().to_string(),
+            "unknown persistence 'dim', expected one of: off, fade, or"
+        );
+        let mut p = Persistence::Off;
+        let names: Vec<String> = (0..4)
+            .map(|_| {
+                p = p.next();
+                p.to_string()
+            })
+            .collect();
+        assert_eq!(names, ["fade", "or", "off", "fade"]);
+    }
+
+    #[test]
+    fn history() {
+        let mut history = FrameHistory::default();
+        assert_eq!(pixels(&history, 0), [(0, 5), (0, 5), (0, 5)]);
+
+        history.push(&[1, 2, 0]);
+        assert_eq!(pixels(&history, 0), [(1, 5), (1, 5), (1, 5)]);
+        history.push(&[0, 1, 0]);
+        // off on the last frame
+        assert_eq!(pixels(&history, 0), [(0, 5), (1, 5), (1, 4)]);
+        // the planes of both frames
+        assert_eq!(pixels(&history, 1), [(1, 5), (3, 5), (1, 5)]);
+        assert_eq!(pixels(&history, 2), [(0, 5), (0, 5), (0, 5)]);
+
+        // fading out over 4 frames
+        let mut faded = Vec::new();
+        for _ in 0..4 {
+            history.push(&[0, 0, 0]);
+            faded.push(pixels(&history, 0));
+        }
+        assert_eq!(
+            faded,
+            [
+                [(0, 5), (0, 5), (1, 3)],
+                [(0, 5), (0, 5), (1, 2)],
+                [(0, 5), (0, 5), (1, 1)],
+                [(0, 5), (0, 5), (0, 5)],
+            ]
+        );
+        assert_eq!(pixels(&history, 1), [(0, 5), (0, 5), (1, 1)]);
+
+        // lit again at full brightness
+        history.push(&[2, 0, 0]);
+        assert_eq!(pixels(&history, 0), [(2, 5), (2, 5), (2, 5)]);
+
+        // a frame of another resolution starts over
+        history.push(&[0; 4]);
+        assert_eq!(pixels(&history, 0), [(0, 5), (0, 5), (0, 5)]);
+    }
+}
@@ -0,0 +1,462 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time;
+
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, Texture};
+use sdl2::video::FullscreenType;
+
+use chip8::{Chip8, CpuError, Rewind, StepOutcome};
+
+use crate::audio::{AudioOptions, Buzzer};
+use crate::capture::{self, CaptureOptions, Clip};
+use crate::debugger::Debugger;
+use crate::gamepad::Gamepads;
+use crate::input::{InputSource, Live, Recorder};
+use crate::keymap::Keymap;
+use crate::palette::Rgb;
+use crate::persistence::{FrameHistory, Persistence};
+
+fn sdl_color(c: Rgb) -> Color {
+    Color::RGB(c.0, c.1, c.2)
+}
+
+pub struct Options {
+    /// Colors, initial scale and grid of the display, also used by the
+    /// captures
+    pub capture: CaptureOptions,
+    /// Scales the display by whole numbers only, so all its pixels have
+    /// the same size
+    pub integer_scale: bool,
+    /// Starts the rom paused under the debugger
+    pub debug: bool,
+    /// Frames that can be played back in reverse
+    pub rewind_frames: usize,
+    pub keymap: Keymap,
+    pub persistence: Persistence,
+    /// Records the display from the start
+    pub clip: Option<Clip>,
+    /// PNG file where to write the display at the end
+    pub screenshot: Option<PathBuf>,
+}
+
+pub struct SdlEmulator {
+    sdl_context: sdl2::Sdl,
+    /// The display drawn by the cpu, in its top left corner as it is sized
+    /// for hi-res. Textures are freed with the canvas
+    texture: Texture,
+    canvas: Canvas<sdl2::video::Window>,
+    integer_scale: bool,
+    capture: CaptureOptions,
+    /// Color of the lines between the pixels, if drawn
+    grid: Option<Color>,
+    /// The display being recorded, started and stopped with F4
+    clip: Option<Clip>,
+    screenshot: Option<PathBuf>,
+    /// How pixels going off are shown, changed with F2
+    persistence: Persistence,
+    history: FrameHistory,
+    keymap: Keymap,
+    /// Keys held on the keyboard
+    keyboard: [u8; 16],
+    gamepads: Gamepads,
+    input: Box<dyn InputSource>,
+    /// Whether the keys come from a movie, during which save states and
+    /// rewind are disabled as they would break it
+    replaying: bool,
+    recorder: Option<Recorder>,
+    buzzer: Buzzer,
+    debugger: Option<Debugger>,
+    /// Rom file, the save states are written next to it
+    rom_path: PathBuf,
+    /// Save state slot used by F5 and F9
+    slot: u8,
+    rewind: Rewind,
+    /// Whether the rewind key is held
+    rewinding: bool,
+    chip8: Chip8,
+}
+
+impl SdlEmulator {
+    /// Opens a resizable window for a 64x32 display magnified by
+    /// `opts.capture.scale`. Hi-res displays are drawn in the same window with
+    /// smaller pixels. The keys come from `input`, and are recorded by
+    /// `recorder`
+    pub fn new(
+        c: Chip8,
+        rom_path: &Path,
+        opts: Options,
+        audio: &AudioOptions,
+        input: Box<dyn InputSource>,
+        recorder: Option<Recorder>,
+        replaying: bool,
+    ) -> SdlEmulator {
+        let scale = opts.capture.scale;
+        let sdl_context = sdl2::init().unwrap();
+        let video_subsystem = sdl_context.video().unwrap();
+
+        let window = video_subsystem
+            .window("chip8-rs", (64 * scale) as u32, (32 * scale) as u32)
+            .position_centered()
+            .resizable()
+            .build()
+            .unwrap();
+
+        let canvas = window.into_canvas().build().unwrap();
+        let texture = canvas
+            .texture_creator()
+            .create_texture_streaming(PixelFormatEnum::RGB24, 128, 64)
+            .unwrap();
+
+        let buzzer = Buzzer::new(&sdl_context, audio);
+        let gamepads = Gamepads::new(&sdl_context);
+        let debugger = if opts.debug {
+            Some(Debugger::new(&c))
+        } else {
+            None
+        };
+
+        SdlEmulator {
+            buzzer,
+            sdl_context,
+            texture,
+            canvas,
+            integer_scale: opts.integer_scale,
+            capture: opts.capture,
+            grid: if opts.capture.grid {
+                Some(sdl_color(opts.capture.palette.grid()))
+            } else {
+                None
+            },
+            clip: opts.clip,
+            screenshot: opts.screenshot,
+            persistence: opts.persistence,
+            history: FrameHistory::default(),
+            keymap: opts.keymap,
+            keyboard: [0; 16],
+            gamepads,
+            input,
+            replaying,
+            recorder,
+            debugger,
+            rom_path: rom_path.to_path_buf(),
+            slot: 0,
+            rewind: Rewind::new(opts.rewind_frames),
+            rewinding: false,
+            chip8: c,
+        }
+    }
+    /// Area of the window the display is drawn on: as large as fits
+    /// while keeping its 2:1 aspect ratio, centered between black borders
+    fn display_rect(&self) -> Result<Rect, String> {
+        let (win_w, win_h) = self.canvas.output_size()?;
+        let w = self.chip8.width() as u32;
+        let (mut dst_w, mut dst_h) = if win_w / 2 <= win_h {
+            (win_w, win_w / 2)
+        } else {
+            (win_h * 2, win_h)
+        };
+        // whole pixels, unless the window is smaller than the display
+        if self.integer_scale && dst_w >= w {
+            dst_w -= dst_w % w;
+            dst_h = dst_w / 2;
+        }
+        Ok(Rect::new(
+            ((win_w - dst_w) / 2) as i32,
+            ((win_h - dst_h) / 2) as i32,
+            dst_w.max(1),
+            dst_h.max(1),
+        ))
+    }
+    fn draw_graphics(&mut self) -> Result<(), String> {
+        let (w, h) = (self.chip8.width(), self.chip8.height());
+        let palette = self.capture.palette;
+        let persistence = self.persistence;
+        let history = &self.history;
+        self.texture
+            .with_lock(Rect::new(0, 0, w as u32, h as u32), |buf, pitch| {
+                for y in 0..h {
+                    for x in 0..w {
+                        let (pixel, brightness) = history.pixel(persistence, y * w + x);
+                        let color = palette.colors[pixel as usize & 0x3];
+                        let color = palette.colors[0].mix(color, brightness);
+                        let i = y * pitch + x * 3;
+                        buf[i..i + 3].copy_from_slice(&[color.0, color.1, color.2]);
+                    }
+                }
+            })?;
+
+        let dst = self.display_rect()?;
+        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        self.canvas.clear();
+        self.canvas
+            .copy(&self.texture, Rect::new(0, 0, w as u32, h as u32), dst)?;
+        if let Some(color) = self.grid {
+            self.canvas.set_draw_color(color);
+            for x in capture::grid_lines(dst.width() as usize, w) {
+                let x = dst.x() + x as i32;
+                self.canvas.draw_line((x, dst.y()), (x, dst.bottom() - 1))?;
+            }
+            for y in capture::grid_lines(dst.height() as usize, h) {
+                let y = dst.y() + y as i32;
+                self.canvas.draw_line((dst.x(), y), (dst.right() - 1, y))?;
+            }
+        }
+
+        self.canvas.present();
+        self.chip8.draw_flag = false;
+        Ok(())
+    }
+    fn toggle_fullscreen(&mut self) -> Result<(), String> {
+        let window = self.canvas.window_mut();
+        let fullscreen = match window.fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off,
+        };
+        window.set_fullscreen(fullscreen)
+    }
+    /// Path of the save state file of a slot: the rom path ending with
+    /// `.state` and the slot number
+    fn state_path(&self, slot: u8) -> PathBuf {
+        let mut path = self.rom_path.clone().into_os_string();
+        path.push(format!(".state{}", slot));
+        PathBuf::from(path)
+    }
+    /// First path next to the rom ending with `.<name>N.<ext>` that
+    /// doesn't exist, for the captures taken with hotkeys
+    fn capture_path(&self, name: &str, ext: &str) -> PathBuf {
+        (0..)
+            .map(|n| {
+                let mut path = self.rom_path.clone().into_os_string();
+                path.push(format!(".{}{}.{}", name, n, ext));
+                PathBuf::from(path)
+            })
+            .find(|path| !path.exists())
+            .unwrap()
+    }
+    fn save_state(&self) {
+        let path = self.state_path(self.slot);
+        match fs::write(&path, self.chip8.save_state()) {
+            Ok(()) => println!("Saved state {} to {}", self.slot, path.display()),
+            Err(err) => eprintln!("Error saving {}: {}", path.display(), err),
+        }
+    }
+    fn load_state(&mut self) {
+        let path = self.state_path(self.slot);
+        let result = fs::read(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|state| self.chip8.load_state(&state).map_err(|err| err.to_string()));
+        match result {
+            Ok(()) => println!("Loaded state {} from {}", self.slot, path.display()),
+            Err(err) => eprintln!("Error loading {}: {}", path.display(), err),
+        }
+    }
+    fn take_screenshot(&self, path: &Path) {
+        match capture::screenshot(&self.chip8, &self.capture, path) {
+            Ok(()) => println!("Saved screenshot to {}", path.display()),
+            Err(err) => eprintln!("Error writing {}: {}", path.display(), err),
+        }
+    }
+    fn finish_clip(&mut self) {
+        if let Some(clip) = self.clip.take() {
+            let (frames, path) = (clip.frames(), clip.path().to_path_buf());
+            match clip.finish() {
+                Ok(()) => println!("Captured {} frames to {}", frames, path.display()),
+                Err(err) => eprintln!("Error writing {}: {}", path.display(), err),
+            }
+        }
+    }
+    /// Starts recording the display to a GIF next to the rom, or stops the
+    /// recording
+    fn toggle_clip(&mut self) {
+        if self.clip.is_some() {
+            self.finish_clip();
+            return;
+        }
+        let path = self.capture_path("clip", "gif");
+        match Clip::create(&path, self.capture) {
+            Ok(clip) => {
+                println!("Capturing to {}, F4 stops", path.display());
+                self.clip = Some(clip);
+            }
+            Err(err) => eprintln!("Error writing {}: {}", path.display(), err),
+        }
+    }
+    /// Whether the keys are recorded or replayed from a movie
+    fn in_movie(&self) -> bool {
+        self.replaying || self.recorder.is_some()
+    }
+    /// Handles the window events, returns false when the window is closed
+    fn set_keys(&mut self) -> Result<bool, String> {
+        let mut events = self.sdl_context.event_pump()?;
+        for event in events.poll_iter() {
+            self.gamepads.handle_event(&event);
+            match event {
+                Event::Quit { .. } => {
+                    println!("Quit");
+                    return Ok(false);
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                } => {
+                    if let Some(k) = self.keymap.get(keycode) {
+                        self.keyboard[k as usize] = 1;
+                    }
+                    match keycode {
+                        Keycode::Backspace | Keycode::F9 if self.in_movie() => {
+                            println!("Rewind and save states are disabled in movies")
+                        }
+                        Keycode::Backspace => self.rewinding = true,
+                        Keycode::F2 => {
+                            self.persistence = self.persistence.next();
+                            println!("Persistence {}", self.persistence);
+                            self.chip8.draw_flag = true;
+                        }
+                        Keycode::F11 => {
+                            if let Err(err) = self.toggle_fullscreen() {
+                                eprintln!("Error toggling fullscreen: {}", err);
+                            }
+                        }
+                        Keycode::F4 => self.toggle_clip(),
+                        Keycode::F12 => {
+                            let path = self.capture_path("screenshot", "png");
+                            self.take_screenshot(&path);
+                        }
+                        Keycode::F5 => self.save_state(),
+                        Keycode::F9 => self.load_state(),
+                        Keycode::F6 | Keycode::F7 => {
+                            self.slot = if keycode == Keycode::F6 {
+                                (self.slot + 9) % 10
+                            } else {
+                                (self.slot + 1) % 10
+                            };
+                            println!("State slot {}", self.slot);
+                        }
+                        _ => {}
+                    }
+                    if let Some(debugger) = &mut self.debugger {
+                        match keycode {
+                            Keycode::F8 => debugger.toggle_pause(&self.chip8),
+                            Keycode::F10 => debugger.step(&self.chip8),
+                            _ => {}
+                        }
+                    }
+                }
+                Event::Window {
+                    win_event: WindowEvent::SizeChanged(..) | WindowEvent::Exposed,
+                    ..
+                } => self.chip8.draw_flag = true,
+                Event::KeyUp {
+                    keycode: Some(keycode),
+                    ..
+                } => {
+                    if let Some(k) = self.keymap.get(keycode) {
+                        self.keyboard[k as usize] = 0;
+                    }
+                    if keycode == Keycode::Backspace {
+                        self.rewinding = false;
+                    }
+                    if keycode == Keycode::Escape {
+                        println!("EXIT");
+                        return Ok(false);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(true)
+    }
+    /// Sets the keys of the next frame from the input
+    fn next_keys(&mut self) {
+        // keys held on either the keyboard or a controller
+        let mut held = self.keyboard;
+        self.gamepads.held_keys(&self.keymap, &mut held);
+        let keys = match self.input.next_frame(&held) {
+            Some(keys) => keys,
+            None => {
+                println!("Replay finished, the keyboard is live");
+                self.input = Box::new(Live);
+                self.replaying = false;
+                held
+            }
+        };
+        self.chip8.key = keys;
+        if let Some(recorder) = &mut self.recorder {
+            recorder.push(&keys);
+        }
+    }
+    /// Runs the emulator at 60 frames per second, executing `ipf`
+    /// instructions per frame, until the rom exits or the window is closed.
+    /// The movie and display being recorded are saved at the end, also on
+    /// error.
+    pub fn run(&mut self, ipf: usize) -> Result<(), CpuError> {
+        let result = self.run_frames(ipf);
+        self.finish_clip();
+        if let Some(path) = self.screenshot.take() {
+            self.take_screenshot(&path);
+        }
+        if let Some(recorder) = &self.recorder {
+            match recorder.save() {
+                Ok(()) => println!(
+                    "Recorded {} frames to {}",
+                    recorder.frames(),
+                    recorder.path().display()
+                ),
+                Err(err) => eprintln!("Error writing {}: {}", recorder.path().display(), err),
+            }
+        }
+        result
+    }
+    fn run_frames(&mut self, ipf: usize) -> Result<(), CpuError> {
+        let frame = time::Duration::from_nanos(1_000_000_000 / 60);
+        let mut next_frame = time::Instant::now();
+        loop {
+            if !self.set_keys().unwrap() {
+                return Ok(());
+            }
+            let outcome = if self.rewinding {
+                self.rewind.rewind(&mut self.chip8);
+                Ok(StepOutcome::Executed)
+            } else {
+                if !self.debugger.as_ref().is_some_and(|d| d.is_paused()) {
+                    self.next_keys();
+                }
+                let outcome = match &mut self.debugger {
+                    Some(debugger) => Ok(debugger.run_frame(&mut self.chip8, ipf)),
+                    None => self.chip8.run_frame(ipf),
+                };
+                if !self.debugger.as_ref().is_some_and(|d| d.is_paused()) {
+                    self.rewind.push(&self.chip8);
+                }
+                outcome
+            };
+            if outcome? == StepOutcome::Exit {
+                println!("EXIT");
+                return Ok(());
+            }
+            self.buzzer.update(&self.chip8);
+            self.history.push(&self.chip8.gfx);
+            if let Some(clip) = &mut self.clip {
+                if let Err(err) = clip.push(&self.chip8) {
+                    eprintln!("Error capturing to {}: {}", clip.path().display(), err);
+                    self.clip = None;
+                }
+            }
+            // fading pixels change on every frame
+            if self.chip8.draw_flag || self.persistence != Persistence::Off {
+                self.draw_graphics().unwrap();
+            }
+            next_frame += frame;
+            let now = time::Instant::now();
+            if next_frame > now {
+                std::thread::sleep(next_frame - now);
+            } else {
+                next_frame = now;
+            }
+        }
+    }
+}